@@ -1,40 +1,78 @@
-use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
-use bevy::ecs::component::Component;
+use bevy::ecs::{component::Component, system::EntityCommands};
 use bevy::math::*;
+use bevy::pbr::{OpaqueRendererMethod, ParallaxMappingMethod};
 use bevy::prelude::*;
+use bevy::render::{mesh::VertexAttributeValues, primitives::Aabb};
 use bevy::utils::{HashMap, HashSet};
 
+use crate::{hierarchy::descendants, schedule::SceneSet, stable_hash::StableHasher};
+
 pub struct AutoInstancePlugin;
 impl Plugin for AutoInstancePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (apply_auto_instance_recursive, consolidate_mesh_instances),
-        );
+        app.init_resource::<AutoInstanceSettings>()
+            .init_resource::<AutoInstanceStats>()
+            .add_systems(
+                Update,
+                (apply_auto_instance_recursive, consolidate_mesh_instances)
+                    .in_set(SceneSet::Instancing),
+            )
+            .add_systems(
+                Update,
+                warn_if_nothing_instanced.after(SceneSet::Instancing),
+            );
     }
 }
 
+#[derive(Resource, Clone, Default)]
+pub struct AutoInstanceSettings {
+    /// Remove duplicate mesh assets once their entities use the canonical mesh. The asset is
+    /// removed directly since the glTF scene still holds handles to it, so this is off by
+    /// default: any entity outside the marked hierarchies, or spawned later from the `Gltf`,
+    /// that still uses a removed mesh loses it. Only enable this when every user of the scene's
+    /// meshes is instanced.
+    pub release_duplicate_meshes: bool,
+    /// Print how many mesh draws collapse into instanced draws once consolidation settles.
+    pub report_instanced_draws: bool,
+    /// Warn about meshes that weren't instanced because their data differs, but whose vertex
+    /// positions are on average closer than this to a mesh with the same vertex layout.
+    pub warn_unmatched_distance: Option<f32>,
+    /// Also instance meshes that are uniformly scaled copies of another mesh, moving the scale
+    /// into the entity's `Transform`.
+    pub detect_scale: bool,
+    /// Only merge meshes across entities whose materials hash equal too, so each group of
+    /// entities sharing a mesh is a set of fully identical draws. Entities that already shared
+    /// a mesh asset in the scene keep sharing it.
+    pub instance_by_material: bool,
+}
+
+/// How many entities have been pointed at a shared mesh or material so far.
+#[derive(Resource, Default)]
+pub struct AutoInstanceStats {
+    pub mesh_merges: u32,
+    pub material_merges: u32,
+}
+
 #[derive(Default)]
 pub struct AutoInstanceMaterialPlugin<M: Material + MaterialHash>(pub PhantomData<M>);
 impl<M: Material + MaterialHash> Plugin for AutoInstanceMaterialPlugin<M> {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, consolidate_material_instances::<M>);
-    }
-}
-
-pub fn all_children<F: FnMut(Entity)>(
-    children: &Children,
-    children_query: &Query<&Children>,
-    closure: &mut F,
-) {
-    for child in children {
-        if let Ok(children) = children_query.get(*child) {
-            all_children(children, children_query, closure);
-        }
-        closure(*child);
+        // Also initialized here so the material plugin works without AutoInstancePlugin
+        app.init_resource::<AutoInstanceSettings>()
+            .init_resource::<AutoInstanceStats>()
+            .add_systems(
+                Update,
+                (
+                    consolidate_material_instances::<M>,
+                    report_instanced_draws::<M>.run_if(|settings: Res<AutoInstanceSettings>| {
+                        settings.report_instanced_draws
+                    }),
+                )
+                    .in_set(SceneSet::Instancing),
+            );
     }
 }
 
@@ -50,27 +88,56 @@ pub struct AutoInstanceMesh;
 #[derive(Component)]
 pub struct AutoInstanceMeshRecursive;
 
+/// The [`MaterialHash`] of the entity's material, added by material consolidation when
+/// [`AutoInstanceSettings::instance_by_material`] is set so meshes are only merged between
+/// entities that draw with the same material.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MaterialInstanceKey(pub u64);
+
+/// Added to entities whose `Handle<A>` was redirected to a shared canonical asset.
+/// `canonical` is the entity the canonical handle was first found on.
+#[derive(Component)]
+pub struct WasInstanced<A: Asset> {
+    pub canonical: Entity,
+    marker: PhantomData<A>,
+}
+
+impl<A: Asset> WasInstanced<A> {
+    pub fn new(canonical: Entity) -> Self {
+        Self {
+            canonical,
+            marker: PhantomData,
+        }
+    }
+}
+
 pub fn apply_auto_instance_recursive(
     mut commands: Commands,
     material_entities: Query<Entity, With<AutoInstanceMaterialRecursive>>,
     mesh_entities: Query<Entity, With<AutoInstanceMeshRecursive>>,
     children_query: Query<&Children>,
+    has_mesh: Query<(), With<Handle<Mesh>>>,
 ) {
+    // Only entities with a mesh are marked, so markers left behind mean something went unhandled
     for entity in &material_entities {
-        if let Ok(children) = children_query.get(entity) {
-            all_children(children, &children_query, &mut |entity| {
-                commands.entity(entity).insert(AutoInstanceMaterial);
-            });
+        if children_query.contains(entity) {
+            for entity in descendants(entity, &children_query) {
+                if has_mesh.contains(entity) {
+                    commands.entity(entity).insert(AutoInstanceMaterial);
+                }
+            }
             commands
                 .entity(entity)
                 .remove::<AutoInstanceMaterialRecursive>();
         }
     }
     for entity in &mesh_entities {
-        if let Ok(children) = children_query.get(entity) {
-            all_children(children, &children_query, &mut |entity| {
-                commands.entity(entity).insert(AutoInstanceMesh);
-            });
+        if children_query.contains(entity) {
+            for entity in descendants(entity, &children_query) {
+                if has_mesh.contains(entity) {
+                    commands.entity(entity).insert(AutoInstanceMesh);
+                }
+            }
             commands
                 .entity(entity)
                 .remove::<AutoInstanceMeshRecursive>();
@@ -78,25 +145,44 @@ pub fn apply_auto_instance_recursive(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn consolidate_material_instances<M: Material + MaterialHash>(
     mut commands: Commands,
     materials: ResMut<Assets<M>>,
     entities: Query<(Entity, &Handle<M>), With<AutoInstanceMaterial>>,
-    mut instances: Local<HashMap<u64, Handle<M>>>,
+    settings: Res<AutoInstanceSettings>,
+    mut stats: ResMut<AutoInstanceStats>,
+    mut instances: Local<HashMap<u64, (Handle<M>, Entity)>>,
     mut handles: Local<HashSet<Handle<M>>>,
     mut count: Local<u32>,
 ) {
     let mut print = false;
-    for (entity, mat_h) in &entities {
+    // Visit in a stable order so the same scene always collapses onto the same canonical materials
+    let mut entities = entities.iter().collect::<Vec<_>>();
+    entities.sort_by_key(|(entity, _)| *entity);
+    for (entity, mat_h) in entities {
         if let Some(mat) = materials.get(mat_h) {
+            if settings.instance_by_material {
+                commands
+                    .entity(entity)
+                    .insert(MaterialInstanceKey(mat.generate_hash()));
+            }
             if !handles.contains(mat_h) {
                 print = true;
                 let h = mat.generate_hash();
-                if let Some(instance_h) = instances.get(&h) {
-                    commands.entity(entity).insert(instance_h.clone());
+                if let Some((instance_h, canonical)) = instances.get(&h) {
+                    debug!(
+                        "Material {:?} merged onto {:?}",
+                        mat_h.id(),
+                        instance_h.id()
+                    );
+                    commands
+                        .entity(entity)
+                        .insert((instance_h.clone(), WasInstanced::<M>::new(*canonical)));
                     *count += 1;
+                    stats.material_merges += 1;
                 } else {
-                    instances.insert(h, mat_h.clone());
+                    instances.insert(h, (mat_h.clone(), entity));
                     handles.insert(mat_h.clone());
                 }
             }
@@ -104,19 +190,21 @@ pub fn consolidate_material_instances<M: Material + MaterialHash>(
         }
     }
     if print {
-        println!("Duplicate material instances found: {}", *count);
-        println!("Total unique materials: {}", instances.len());
+        info!("Duplicate material instances found: {}", *count);
+        info!("Total unique materials: {}", instances.len());
     }
 }
 
 // Implement the MaterialHash trait for any material
+/// Implementations should hash with [`StableHasher`] so the result doesn't change between
+/// toolchains. Texture handles hash by asset id, which is only stable within one run.
 pub trait MaterialHash {
     fn generate_hash(&self) -> u64;
 }
 
 impl MaterialHash for StandardMaterial {
     fn generate_hash(&self) -> u64 {
-        let state = &mut DefaultHasher::new();
+        let state = &mut StableHasher::new();
         hash_color(&self.base_color, state);
         self.base_color_texture.hash(state);
         hash_color(&self.emissive, state);
@@ -151,21 +239,27 @@ impl MaterialHash for StandardMaterial {
         self.depth_bias.to_bits().hash(state);
         self.depth_map.hash(state);
         self.parallax_depth_scale.to_bits().hash(state);
-        self.parallax_mapping_method
-            .reflect_hash()
-            .unwrap()
-            .hash(state);
+        match self.parallax_mapping_method {
+            ParallaxMappingMethod::Occlusion => 572093481.hash(state),
+            ParallaxMappingMethod::Relief { max_steps } => {
+                839105726.hash(state);
+                max_steps.hash(state);
+            }
+        }
         self.max_parallax_layer_count.to_bits().hash(state);
-        self.opaque_render_method
-            .reflect_hash()
-            .unwrap()
-            .hash(state);
+        match self.opaque_render_method {
+            OpaqueRendererMethod::Forward => 613920574.hash(state),
+            OpaqueRendererMethod::Deferred => 290461837.hash(state),
+            OpaqueRendererMethod::Auto => 748201396.hash(state),
+        }
         self.deferred_lighting_pass_id.hash(state);
         self.lightmap_exposure.to_bits().hash(state);
         state.finish()
     }
 }
 
+/// Hashes the linear RGBA values, so the same color hashes equal whichever `Color` variant it
+/// was built as.
 pub fn hash_color<H: Hasher>(color: &Color, state: &mut H) {
     #[cfg(feature = "bevy_main")]
     {
@@ -176,49 +270,977 @@ pub fn hash_color<H: Hasher>(color: &Color, state: &mut H) {
         color.alpha().to_bits().hash(state);
     }
     #[cfg(not(feature = "bevy_main"))]
-    {
-        color.r().to_bits().hash(state);
-        color.g().to_bits().hash(state);
-        color.b().to_bits().hash(state);
-        color.a().to_bits().hash(state);
+    for channel in color.as_linear_rgba_f32() {
+        channel.to_bits().hash(state);
     }
 }
 
+/// Frames a mesh waits for its material to be hashed with
+/// [`AutoInstanceSettings::instance_by_material`] before it's instanced without grouping by
+/// material, in case no [`AutoInstanceMaterialPlugin`] handles its material type.
+const MATERIAL_KEY_WAIT_FRAMES: u32 = 120;
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn consolidate_mesh_instances(
     mut commands: Commands,
-    meshes: ResMut<Assets<Mesh>>,
-    mut entities: Query<(Entity, &Handle<Mesh>), With<AutoInstanceMesh>>,
-    mut instances: Local<HashMap<u64, Handle<Mesh>>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<AutoInstanceSettings>,
+    mut stats: ResMut<AutoInstanceStats>,
+    entities: Query<
+        (
+            Entity,
+            &Handle<Mesh>,
+            Option<&Transform>,
+            Option<&MaterialInstanceKey>,
+            Has<AutoInstanceMaterial>,
+        ),
+        With<AutoInstanceMesh>,
+    >,
+    mut instances: Local<HashMap<u64, (Handle<Mesh>, Entity)>>,
     mut handles: Local<HashSet<Handle<Mesh>>>,
+    // The canonical mesh each released duplicate was merged onto and its scale relative to it
+    mut released: Local<HashMap<AssetId<Mesh>, (Handle<Mesh>, Entity, f32)>>,
+    mut layouts: Local<HashMap<u64, Vec<Handle<Mesh>>>>,
+    // Canonical meshes grouped by `unscaled_attributes_hash`, for `detect_scale`
+    mut unscaled: Local<HashMap<u64, Vec<(Handle<Mesh>, Entity)>>>,
+    // Entities per (mesh, material) pair, for `instance_by_material`
+    mut draw_groups: Local<HashMap<(AssetId<Mesh>, Option<MaterialInstanceKey>), u32>>,
+    // Frames each entity has waited for its `MaterialInstanceKey`
+    mut material_waits: Local<HashMap<Entity, u32>>,
     mut count: Local<u32>,
 ) {
     let mut print = false;
-    for (entity, mesh_h) in &mut entities {
+    let mut gave_up_waiting = 0;
+    // Visit in a stable order so the same scene always collapses onto the same canonical meshes
+    let mut entities = entities.iter().collect::<Vec<_>>();
+    entities.sort_by_key(|(entity, ..)| *entity);
+    material_waits.retain(|waiting, _| {
+        entities
+            .binary_search_by_key(waiting, |(entity, ..)| *entity)
+            .is_ok()
+    });
+    for (entity, mesh_h, transform, material_key, material_pending) in entities {
+        // Wait for the material to be hashed so the mesh can be grouped by it
+        if settings.instance_by_material && material_pending {
+            let frames = material_waits.entry(entity).or_default();
+            *frames += 1;
+            if *frames <= MATERIAL_KEY_WAIT_FRAMES {
+                continue;
+            }
+            if *frames == MATERIAL_KEY_WAIT_FRAMES + 1 {
+                gave_up_waiting += 1;
+            }
+        }
+        let material_key = material_key
+            .copied()
+            .filter(|_| settings.instance_by_material);
+        // This duplicate was already released, point it at the canonical mesh
+        if let Some((instance_h, canonical, scale)) = released.get(&mesh_h.id()) {
+            *draw_groups
+                .entry((instance_h.id(), material_key))
+                .or_default() += 1;
+            let mut entity = commands.entity(entity);
+            entity
+                .insert((instance_h.clone(), WasInstanced::<Mesh>::new(*canonical)))
+                .remove::<AutoInstanceMesh>();
+            if let (Some(transform), true) = (transform, *scale != 1.0) {
+                entity.insert(transform.with_scale(transform.scale * *scale));
+                set_canonical_aabb(&mut entity, meshes.get(instance_h));
+            }
+            *count += 1;
+            stats.mesh_merges += 1;
+            print = true;
+            continue;
+        }
         if let Some(mesh) = meshes.get(mesh_h) {
             if !handles.contains(mesh_h) {
+                // Without positions any two meshes with the same other attributes would match,
+                // so these are left alone. Adding the handle to `handles` only warns once.
+                if mesh.attribute(Mesh::ATTRIBUTE_POSITION).is_none() {
+                    warn!(
+                        "Mesh {:?} has no position attribute, it won't be instanced",
+                        mesh_h.id()
+                    );
+                    handles.insert(mesh_h.clone());
+                    commands.entity(entity).remove::<AutoInstanceMesh>();
+                    continue;
+                }
                 print = true;
-                let state = &mut DefaultHasher::new();
+                let h = with_material_key(mesh_instance_hash(mesh), material_key);
 
-                mesh.attributes().count().hash(state);
-                for (id, attribute) in mesh.attributes() {
-                    id.hash(state);
-                    attribute.get_bytes().hash(state);
-                }
-                let h = state.finish();
+                let unscaled_h = with_material_key(unscaled_attributes_hash(mesh), material_key);
+                let scaled = match (settings.detect_scale, transform) {
+                    (true, Some(transform)) => {
+                        unscaled.get(&unscaled_h).into_iter().flatten().find_map(
+                            |(instance_h, canonical)| {
+                                let scale = uniform_scale_between(meshes.get(instance_h)?, mesh)?;
+                                Some((instance_h.clone(), *canonical, scale, transform))
+                            },
+                        )
+                    }
+                    _ => None,
+                };
 
-                if let Some(instance_h) = instances.get(&h) {
-                    commands.entity(entity).insert(instance_h.clone());
+                if let Some((instance_h, canonical)) = instances.get(&h) {
+                    debug!("Mesh {:?} merged onto {:?}", mesh_h.id(), instance_h.id());
+                    *draw_groups
+                        .entry((instance_h.id(), material_key))
+                        .or_default() += 1;
+                    commands
+                        .entity(entity)
+                        .insert((instance_h.clone(), WasInstanced::<Mesh>::new(*canonical)));
+                    *count += 1;
+                    stats.mesh_merges += 1;
+                    if settings.release_duplicate_meshes {
+                        meshes.remove(mesh_h);
+                        released.insert(mesh_h.id(), (instance_h.clone(), *canonical, 1.0));
+                    }
+                } else if let Some((instance_h, canonical, scale, transform)) = scaled {
+                    debug!(
+                        "Mesh {:?} merged onto {:?} with scale {scale}",
+                        mesh_h.id(),
+                        instance_h.id()
+                    );
+                    *draw_groups
+                        .entry((instance_h.id(), material_key))
+                        .or_default() += 1;
+                    let mut entity = commands.entity(entity);
+                    entity.insert((
+                        instance_h.clone(),
+                        WasInstanced::<Mesh>::new(canonical),
+                        transform.with_scale(transform.scale * scale),
+                    ));
+                    // The old bounds were for the unscaled mesh
+                    set_canonical_aabb(&mut entity, meshes.get(&instance_h));
                     *count += 1;
+                    stats.mesh_merges += 1;
+                    if settings.release_duplicate_meshes {
+                        meshes.remove(mesh_h);
+                        released.insert(mesh_h.id(), (instance_h, canonical, scale));
+                    }
                 } else {
-                    instances.insert(h, mesh_h.clone());
+                    if let Some(max_distance) = settings.warn_unmatched_distance {
+                        let similar = layouts.entry(vertex_layout_hash(mesh)).or_default();
+                        warn_unmatched_mesh(mesh_h, similar, &meshes, max_distance);
+                        similar.push(mesh_h.clone());
+                    }
+                    *draw_groups.entry((mesh_h.id(), material_key)).or_default() += 1;
+                    instances.insert(h, (mesh_h.clone(), entity));
                     handles.insert(mesh_h.clone());
+                    if settings.detect_scale {
+                        unscaled
+                            .entry(unscaled_h)
+                            .or_default()
+                            .push((mesh_h.clone(), entity));
+                    }
                 }
+            } else {
+                *draw_groups.entry((mesh_h.id(), material_key)).or_default() += 1;
             }
             commands.entity(entity).remove::<AutoInstanceMesh>();
         }
     }
+    if gave_up_waiting > 0 {
+        warn!(
+            "{gave_up_waiting} meshes waited {MATERIAL_KEY_WAIT_FRAMES} frames for their material \
+            to be hashed, instancing them without grouping by material. Is an \
+            AutoInstanceMaterialPlugin added for their material type?"
+        );
+    }
     if print {
-        println!("Duplicate mesh instances found: {}", *count);
-        println!("Total unique meshes: {}", instances.len());
+        info!("Duplicate mesh instances found: {}", *count);
+        info!("Total unique meshes: {}", instances.len());
+        if settings.release_duplicate_meshes {
+            info!("Duplicate mesh assets released: {}", released.len());
+        }
+        if settings.instance_by_material {
+            info!(
+                "Fully identical mesh and material groups: {}",
+                draw_groups
+                    .values()
+                    .filter(|entities| **entities > 1)
+                    .count()
+            );
+        }
+    }
+}
+
+/// Gives an entity moved onto a scaled canonical mesh that mesh's bounds, or removes its `Aabb`
+/// so `calculate_bounds` recomputes it.
+fn set_canonical_aabb(entity: &mut EntityCommands, canonical: Option<&Mesh>) {
+    match canonical.and_then(Mesh::compute_aabb) {
+        Some(aabb) => entity.insert(aabb),
+        None => entity.remove::<Aabb>(),
+    };
+}
+
+/// Hash of every vertex attribute's data, meshes with equal hashes are merged onto one shared
+/// mesh by [`consolidate_mesh_instances`].
+pub fn mesh_instance_hash(mesh: &Mesh) -> u64 {
+    let state = &mut StableHasher::new();
+    mesh.attributes().count().hash(state);
+    for (id, attribute) in mesh.attributes() {
+        id.hash(state);
+        attribute.get_bytes().hash(state);
+    }
+    state.finish()
+}
+
+/// Combines a mesh hash with the entity's material so meshes only match within one material.
+fn with_material_key(hash: u64, material_key: Option<MaterialInstanceKey>) -> u64 {
+    let Some(material_key) = material_key else {
+        return hash;
+    };
+    let state = &mut StableHasher::new();
+    hash.hash(state);
+    material_key.hash(state);
+    state.finish()
+}
+
+/// Hash of the vertex count and attributes, used to group meshes that could be near duplicates.
+pub fn vertex_layout_hash(mesh: &Mesh) -> u64 {
+    let state = &mut StableHasher::new();
+    mesh.count_vertices().hash(state);
+    for (id, _) in mesh.attributes() {
+        id.hash(state);
+    }
+    state.finish()
+}
+
+/// Hash of every attribute but the vertex positions, so uniformly scaled copies of a mesh hash
+/// equal. Normals and tangents don't change under uniform scale.
+pub fn unscaled_attributes_hash(mesh: &Mesh) -> u64 {
+    let state = &mut StableHasher::new();
+    mesh.count_vertices().hash(state);
+    for (id, attribute) in mesh.attributes() {
+        id.hash(state);
+        if id != Mesh::ATTRIBUTE_POSITION.id {
+            attribute.get_bytes().hash(state);
+        }
+    }
+    state.finish()
+}
+
+/// Mean distance of the vertex positions from the mesh origin.
+pub fn mean_vertex_length(positions: &[[f32; 3]]) -> f32 {
+    positions
+        .iter()
+        .map(|position| Vec3::from(*position).length())
+        .sum::<f32>()
+        / positions.len() as f32
+}
+
+/// Average vertex error, relative to the mesh size, below which a mesh counts as a scaled copy.
+const SCALE_MATCH_TOLERANCE: f32 = 1e-4;
+
+/// The uniform scale that turns `canonical` into `mesh`, if `mesh` is a scaled copy of it.
+pub fn uniform_scale_between(canonical: &Mesh, mesh: &Mesh) -> Option<f32> {
+    let (Some(VertexAttributeValues::Float32x3(a)), Some(VertexAttributeValues::Float32x3(b))) = (
+        canonical.attribute(Mesh::ATTRIBUTE_POSITION),
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+    ) else {
+        return None;
+    };
+    if a.is_empty() || a.len() != b.len() {
+        return None;
+    }
+    let size = mean_vertex_length(b);
+    let scale = size / mean_vertex_length(a);
+    if !scale.is_normal() {
+        return None;
+    }
+    let error = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (Vec3::from(*a) * scale).distance(Vec3::from(*b)))
+        .sum::<f32>()
+        / a.len() as f32;
+    (error <= size * SCALE_MATCH_TOLERANCE).then_some(scale)
+}
+
+/// Mean distance between corresponding vertex positions of two meshes with the same layout.
+pub fn average_vertex_distance(a: &Mesh, b: &Mesh) -> Option<f32> {
+    let (Some(VertexAttributeValues::Float32x3(a)), Some(VertexAttributeValues::Float32x3(b))) = (
+        a.attribute(Mesh::ATTRIBUTE_POSITION),
+        b.attribute(Mesh::ATTRIBUTE_POSITION),
+    ) else {
+        return None;
+    };
+    if a.is_empty() || a.len() != b.len() {
+        return None;
+    }
+    let total = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| Vec3::from(*a).distance(Vec3::from(*b)))
+        .sum::<f32>();
+    Some(total / a.len() as f32)
+}
+
+fn warn_unmatched_mesh(
+    mesh_h: &Handle<Mesh>,
+    similar: &[Handle<Mesh>],
+    meshes: &Assets<Mesh>,
+    max_distance: f32,
+) {
+    let Some(mesh) = meshes.get(mesh_h) else {
+        return;
+    };
+    for other_h in similar {
+        let Some(other) = meshes.get(other_h) else {
+            continue;
+        };
+        if let Some(distance) = average_vertex_distance(mesh, other) {
+            if distance <= max_distance {
+                warn!(
+                    "Mesh {:?} wasn't instanced but is close to {:?}, average vertex distance {}",
+                    mesh_h.id(),
+                    other_h.id(),
+                    distance
+                );
+            }
+        }
+    }
+}
+
+/// Frames the remaining `AutoInstanceMaterial` markers have to stay unchanged before they're
+/// considered unhandled.
+const UNHANDLED_MARKER_FRAMES: u32 = 120;
+
+type MeshMarkers = Or<(With<AutoInstanceMesh>, With<AutoInstanceMeshRecursive>)>;
+type MaterialMarkers = Or<(
+    With<AutoInstanceMaterial>,
+    With<AutoInstanceMaterialRecursive>,
+)>;
+
+#[derive(Default)]
+pub struct InstancingCheck {
+    marked: bool,
+    pending_materials: usize,
+    unchanged_frames: u32,
+    done: bool,
+}
+
+/// Warns once after the first consolidation pass if nothing was instanced, or if material markers
+/// are never consumed because no [`AutoInstanceMaterialPlugin`] handles their material type.
+pub fn warn_if_nothing_instanced(
+    mut check: Local<InstancingCheck>,
+    stats: Res<AutoInstanceStats>,
+    mesh_markers: Query<(), MeshMarkers>,
+    material_markers: Query<(), MaterialMarkers>,
+) {
+    let pending_meshes = mesh_markers.iter().len();
+    let pending_materials = material_markers.iter().len();
+    if pending_meshes + pending_materials > 0 {
+        check.marked = true;
+    }
+    if check.done || !check.marked || pending_meshes > 0 {
+        return;
+    }
+    if pending_materials > 0 {
+        if pending_materials == check.pending_materials {
+            check.unchanged_frames += 1;
+        } else {
+            check.pending_materials = pending_materials;
+            check.unchanged_frames = 0;
+        }
+        if check.unchanged_frames < UNHANDLED_MARKER_FRAMES {
+            return;
+        }
+        warn!(
+            "{pending_materials} entities were marked for material instancing but never \
+            consolidated. Is there an AutoInstanceMaterialPlugin for their material type?"
+        );
+    }
+    if stats.mesh_merges + stats.material_merges == 0 {
+        warn!("Auto instancing is enabled but no duplicate meshes or materials were found");
+    }
+    check.done = true;
+}
+
+/// Bevy sorts opaque draws by pipeline and mesh, then merges neighbours that share a mesh and
+/// material bind group into a single instanced draw. Once the consolidation above has pointed
+/// duplicates at the same handles they are instanced automatically, while each entity is still
+/// frustum culled on its own. This reports the resulting draw counts, assuming everything is
+/// visible and every (mesh, material) pair shares one pipeline.
+#[allow(clippy::type_complexity)]
+pub fn report_instanced_draws<M: Material>(
+    mut pending: Local<bool>,
+    unprocessed: Query<
+        (),
+        Or<(
+            With<AutoInstanceMesh>,
+            With<AutoInstanceMeshRecursive>,
+            With<AutoInstanceMaterial>,
+            With<AutoInstanceMaterialRecursive>,
+        )>,
+    >,
+    entities: Query<(&Handle<Mesh>, &Handle<M>)>,
+) {
+    // Wait until a round of consolidation has finished before reporting
+    if !unprocessed.is_empty() {
+        *pending = true;
+        return;
+    }
+    if !*pending {
+        return;
+    }
+    *pending = false;
+    let draws = entities.iter().len();
+    let batches = entities
+        .iter()
+        .map(|(mesh_h, mat_h)| (mesh_h.id(), mat_h.id()))
+        .collect::<HashSet<_>>()
+        .len();
+    info!(
+        "Instanced draws: {} mesh draws in {} batches ({} fewer draw calls)",
+        draws,
+        batches,
+        draws - batches
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Face, PrimitiveTopology},
+    };
+
+    use super::*;
+
+    fn image(id: u128) -> Option<Handle<Image>> {
+        Some(Handle::weak_from_u128(id))
+    }
+
+    /// An app that only runs mesh instancing, with `settings`.
+    fn test_app(settings: AutoInstanceSettings) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Mesh>()
+            .insert_resource(settings)
+            .init_resource::<AutoInstanceStats>()
+            .add_systems(Update, consolidate_mesh_instances);
+        app
+    }
+
+    #[test]
+    fn material_plugin_works_on_its_own() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<StandardMaterial>()
+            .add_plugins(AutoInstanceMaterialPlugin::<StandardMaterial>::default());
+
+        let mut materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        let handles = [(); 2].map(|_| materials.add(StandardMaterial::default()));
+        let entities = handles
+            .clone()
+            .map(|material| app.world.spawn((material, AutoInstanceMaterial)).id());
+
+        app.update();
+
+        for entity in entities {
+            assert_eq!(
+                app.world.get::<Handle<StandardMaterial>>(entity),
+                Some(&handles[0])
+            );
+        }
+        assert_eq!(app.world.resource::<AutoInstanceStats>().material_merges, 1);
+    }
+
+    #[test]
+    fn material_hash_is_stable() {
+        let material = StandardMaterial {
+            base_color: Color::rgb(0.2, 0.4, 0.6),
+            alpha_mode: AlphaMode::Mask(0.5),
+            parallax_mapping_method: ParallaxMappingMethod::Relief { max_steps: 4 },
+            opaque_render_method: OpaqueRendererMethod::Deferred,
+            ..default()
+        };
+        // Only changes when a hashed field is added or the hashing changes
+        assert_eq!(material.generate_hash(), 0x6a04_f253_7c5f_66a7);
+    }
+
+    #[test]
+    fn identical_materials_hash_equal() {
+        let a = StandardMaterial {
+            base_color: Color::rgb(0.2, 0.4, 0.6),
+            base_color_texture: image(1),
+            alpha_mode: AlphaMode::Mask(0.5),
+            ..default()
+        };
+        let b = a.clone();
+        assert_eq!(a.generate_hash(), b.generate_hash());
+        assert_eq!(
+            StandardMaterial::default().generate_hash(),
+            StandardMaterial::default().generate_hash()
+        );
+    }
+
+    #[test]
+    fn each_hashed_field_changes_hash() {
+        let base = StandardMaterial::default();
+        let variants = [
+            (
+                "base_color",
+                StandardMaterial {
+                    base_color: Color::RED,
+                    ..default()
+                },
+            ),
+            (
+                "base_color_texture",
+                StandardMaterial {
+                    base_color_texture: image(1),
+                    ..default()
+                },
+            ),
+            (
+                "emissive",
+                StandardMaterial {
+                    emissive: Color::WHITE,
+                    ..default()
+                },
+            ),
+            (
+                "emissive_texture",
+                StandardMaterial {
+                    emissive_texture: image(2),
+                    ..default()
+                },
+            ),
+            (
+                "perceptual_roughness",
+                StandardMaterial {
+                    perceptual_roughness: 0.1,
+                    ..default()
+                },
+            ),
+            (
+                "metallic",
+                StandardMaterial {
+                    metallic: 1.0,
+                    ..default()
+                },
+            ),
+            (
+                "metallic_roughness_texture",
+                StandardMaterial {
+                    metallic_roughness_texture: image(3),
+                    ..default()
+                },
+            ),
+            (
+                "reflectance",
+                StandardMaterial {
+                    reflectance: 0.9,
+                    ..default()
+                },
+            ),
+            (
+                "diffuse_transmission",
+                StandardMaterial {
+                    diffuse_transmission: 0.6,
+                    ..default()
+                },
+            ),
+            (
+                "specular_transmission",
+                StandardMaterial {
+                    specular_transmission: 0.6,
+                    ..default()
+                },
+            ),
+            (
+                "thickness",
+                StandardMaterial {
+                    thickness: 0.2,
+                    ..default()
+                },
+            ),
+            (
+                "ior",
+                StandardMaterial {
+                    ior: 1.33,
+                    ..default()
+                },
+            ),
+            (
+                "attenuation_distance",
+                StandardMaterial {
+                    attenuation_distance: 10.0,
+                    ..default()
+                },
+            ),
+            (
+                "attenuation_color",
+                StandardMaterial {
+                    attenuation_color: Color::BLUE,
+                    ..default()
+                },
+            ),
+            (
+                "normal_map_texture",
+                StandardMaterial {
+                    normal_map_texture: image(4),
+                    ..default()
+                },
+            ),
+            (
+                "flip_normal_map_y",
+                StandardMaterial {
+                    flip_normal_map_y: true,
+                    ..default()
+                },
+            ),
+            (
+                "occlusion_texture",
+                StandardMaterial {
+                    occlusion_texture: image(5),
+                    ..default()
+                },
+            ),
+            (
+                "double_sided",
+                StandardMaterial {
+                    double_sided: true,
+                    ..default()
+                },
+            ),
+            (
+                "cull_mode",
+                StandardMaterial {
+                    cull_mode: Some(Face::Front),
+                    ..default()
+                },
+            ),
+            (
+                "unlit",
+                StandardMaterial {
+                    unlit: true,
+                    ..default()
+                },
+            ),
+            (
+                "fog_enabled",
+                StandardMaterial {
+                    fog_enabled: false,
+                    ..default()
+                },
+            ),
+            (
+                "alpha_mode",
+                StandardMaterial {
+                    alpha_mode: AlphaMode::Blend,
+                    ..default()
+                },
+            ),
+            (
+                "depth_bias",
+                StandardMaterial {
+                    depth_bias: 1.0,
+                    ..default()
+                },
+            ),
+            (
+                "depth_map",
+                StandardMaterial {
+                    depth_map: image(6),
+                    ..default()
+                },
+            ),
+            (
+                "parallax_depth_scale",
+                StandardMaterial {
+                    parallax_depth_scale: 0.5,
+                    ..default()
+                },
+            ),
+            (
+                "parallax_mapping_method",
+                StandardMaterial {
+                    parallax_mapping_method: ParallaxMappingMethod::Relief { max_steps: 4 },
+                    ..default()
+                },
+            ),
+            (
+                "max_parallax_layer_count",
+                StandardMaterial {
+                    max_parallax_layer_count: 32.0,
+                    ..default()
+                },
+            ),
+            (
+                "opaque_render_method",
+                StandardMaterial {
+                    opaque_render_method: OpaqueRendererMethod::Deferred,
+                    ..default()
+                },
+            ),
+            (
+                "deferred_lighting_pass_id",
+                StandardMaterial {
+                    deferred_lighting_pass_id: 2,
+                    ..default()
+                },
+            ),
+            (
+                "lightmap_exposure",
+                StandardMaterial {
+                    lightmap_exposure: 2.0,
+                    ..default()
+                },
+            ),
+        ];
+        for (field, material) in variants {
+            assert_ne!(
+                base.generate_hash(),
+                material.generate_hash(),
+                "changing {field} did not change the hash"
+            );
+        }
+    }
+
+    #[test]
+    fn color_variants_hash_equal() {
+        let srgb = Color::rgba(0.5, 0.25, 0.75, 0.8);
+        let [r, g, b, a] = srgb.as_linear_rgba_f32();
+        let linear = Color::rgba_linear(r, g, b, a);
+        let hash = |base_color| {
+            StandardMaterial {
+                base_color,
+                ..default()
+            }
+            .generate_hash()
+        };
+        assert_eq!(hash(srgb), hash(linear));
+        // HDR values above 1.0 are kept rather than clamped
+        assert_ne!(
+            hash(Color::rgb_linear(2.0, 1.0, 1.0)),
+            hash(Color::rgb_linear(1.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn alpha_modes_hash_differently() {
+        let hashes = [
+            AlphaMode::Opaque,
+            AlphaMode::Mask(0.5),
+            AlphaMode::Mask(0.25),
+            AlphaMode::Blend,
+            AlphaMode::Premultiplied,
+            AlphaMode::Add,
+            AlphaMode::Multiply,
+        ]
+        .map(|alpha_mode| {
+            StandardMaterial {
+                alpha_mode,
+                ..default()
+            }
+            .generate_hash()
+        });
+        let unique = hashes.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), hashes.len());
+    }
+
+    #[test]
+    fn mesh_instance_hash_matches_identical_meshes() {
+        let a = Mesh::from(Cuboid::default());
+        assert_eq!(
+            mesh_instance_hash(&a),
+            mesh_instance_hash(&Mesh::from(Cuboid::default()))
+        );
+        assert_ne!(
+            mesh_instance_hash(&a),
+            mesh_instance_hash(&Mesh::from(Cuboid::from_size(Vec3::splat(2.0))))
+        );
+        let without_uvs = a.clone().with_removed_attribute(Mesh::ATTRIBUTE_UV_0);
+        assert_ne!(mesh_instance_hash(&a), mesh_instance_hash(&without_uvs));
+        assert_eq!(
+            unscaled_attributes_hash(&a),
+            unscaled_attributes_hash(&Mesh::from(Cuboid::from_size(Vec3::splat(2.0))))
+        );
+    }
+
+    #[test]
+    fn duplicate_meshes_are_released() {
+        let mut app = test_app(AutoInstanceSettings {
+            release_duplicate_meshes: true,
+            ..default()
+        });
+
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let a = meshes.add(Cuboid::default());
+        let b = meshes.add(Cuboid::default());
+        let c = meshes.add(Sphere::default());
+        let entity_a = app.world.spawn((a.clone(), AutoInstanceMesh)).id();
+        let entity_b = app.world.spawn((b.clone(), AutoInstanceMesh)).id();
+        app.world.spawn((c.clone(), AutoInstanceMesh));
+        assert_eq!(app.world.resource::<Assets<Mesh>>().len(), 3);
+
+        app.update();
+
+        let meshes = app.world.resource::<Assets<Mesh>>();
+        assert_eq!(meshes.len(), 2);
+        assert!(meshes.get(&b).is_none());
+        assert_eq!(app.world.get::<Handle<Mesh>>(entity_a), Some(&a));
+        assert_eq!(app.world.get::<Handle<Mesh>>(entity_b), Some(&a));
+        assert!(app.world.get::<WasInstanced<Mesh>>(entity_a).is_none());
+        assert_eq!(
+            app.world
+                .get::<WasInstanced<Mesh>>(entity_b)
+                .map(|instanced| instanced.canonical),
+            Some(entity_a)
+        );
+    }
+
+    #[test]
+    fn scaled_duplicates_are_instanced() {
+        let mut app = test_app(AutoInstanceSettings {
+            release_duplicate_meshes: true,
+            detect_scale: true,
+            ..default()
+        });
+
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let a = meshes.add(Cuboid::default());
+        let b = meshes.add(Cuboid::from_size(Vec3::splat(2.0)));
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0);
+        let entity_a = app
+            .world
+            .spawn((a.clone(), AutoInstanceMesh, transform))
+            .id();
+        // Bounds of the 2x mesh, as calculate_bounds would have added
+        let entity_b = app
+            .world
+            .spawn((
+                b.clone(),
+                AutoInstanceMesh,
+                transform,
+                Aabb::from_min_max(Vec3::splat(-1.0), Vec3::ONE),
+            ))
+            .id();
+
+        app.update();
+
+        let meshes = app.world.resource::<Assets<Mesh>>();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(app.world.get::<Handle<Mesh>>(entity_b), Some(&a));
+        assert_eq!(app.world.get::<Transform>(entity_a), Some(&transform));
+        assert_eq!(
+            app.world.get::<Transform>(entity_b),
+            Some(&transform.with_scale(Vec3::splat(2.0)))
+        );
+        // Scaled by the transform, the canonical mesh's bounds cover the original mesh again
+        let aabb = app.world.get::<Aabb>(entity_b).unwrap();
+        assert_eq!(aabb.center, Vec3A::ZERO);
+        assert_eq!(aabb.half_extents, Vec3A::splat(0.5));
+    }
+
+    #[test]
+    fn meshes_are_only_merged_within_a_material() {
+        let mut app = test_app(AutoInstanceSettings {
+            release_duplicate_meshes: true,
+            instance_by_material: true,
+            ..default()
+        });
+
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let handles = [(); 3].map(|_| meshes.add(Cuboid::default()));
+        let entities = [(&handles[0], 1), (&handles[1], 1), (&handles[2], 2)].map(|(mesh, key)| {
+            app.world
+                .spawn((mesh.clone(), AutoInstanceMesh, MaterialInstanceKey(key)))
+                .id()
+        });
+
+        app.update();
+
+        let mesh = |i: usize| app.world.get::<Handle<Mesh>>(entities[i]);
+        assert_eq!(mesh(0), Some(&handles[0]));
+        assert_eq!(mesh(1), Some(&handles[0]));
+        assert_eq!(mesh(2), Some(&handles[2]));
+        assert_eq!(app.world.resource::<Assets<Mesh>>().len(), 2);
+    }
+
+    #[test]
+    fn meshes_stop_waiting_for_unhandled_materials() {
+        let mut app = test_app(AutoInstanceSettings {
+            instance_by_material: true,
+            ..default()
+        });
+
+        // No material plugin clears AutoInstanceMaterial
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let handles = [(); 2].map(|_| meshes.add(Cuboid::default()));
+        let entities = handles.clone().map(|mesh| {
+            app.world
+                .spawn((mesh, AutoInstanceMesh, AutoInstanceMaterial))
+                .id()
+        });
+
+        for _ in 0..MATERIAL_KEY_WAIT_FRAMES {
+            app.update();
+        }
+        assert!(app.world.get::<AutoInstanceMesh>(entities[0]).is_some());
+        assert_eq!(
+            app.world.get::<Handle<Mesh>>(entities[1]),
+            Some(&handles[1])
+        );
+
+        app.update();
+        for entity in entities {
+            assert!(app.world.get::<AutoInstanceMesh>(entity).is_none());
+            assert_eq!(app.world.get::<Handle<Mesh>>(entity), Some(&handles[0]));
+        }
+    }
+
+    #[test]
+    fn positionless_meshes_are_not_instanced() {
+        let mut app = test_app(AutoInstanceSettings::default());
+
+        let positionless = || {
+            Mesh::new(PrimitiveTopology::PointList, RenderAssetUsages::default())
+                .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0f32, 0.0]; 3])
+        };
+        let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
+        let a = meshes.add(positionless());
+        let b = meshes.add(positionless());
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0);
+        let aabb = Aabb::from_min_max(Vec3::ZERO, Vec3::ONE);
+        let entity_a = app
+            .world
+            .spawn((a.clone(), AutoInstanceMesh, transform, aabb))
+            .id();
+        let entity_b = app
+            .world
+            .spawn((b.clone(), AutoInstanceMesh, transform, aabb))
+            .id();
+
+        app.update();
+
+        assert_eq!(app.world.resource::<Assets<Mesh>>().len(), 2);
+        assert_eq!(app.world.get::<Handle<Mesh>>(entity_a), Some(&a));
+        assert_eq!(app.world.get::<Handle<Mesh>>(entity_b), Some(&b));
+        for entity in [entity_a, entity_b] {
+            assert!(app.world.get::<AutoInstanceMesh>(entity).is_none());
+            assert!(app.world.get::<WasInstanced<Mesh>>(entity).is_none());
+            assert_eq!(app.world.get::<Transform>(entity), Some(&transform));
+            let entity_aabb = app.world.get::<Aabb>(entity).unwrap();
+            assert_eq!(entity_aabb.center, aabb.center);
+            assert_eq!(entity_aabb.half_extents, aabb.half_extents);
+        }
+    }
+
+    #[test]
+    fn parallax_relief_steps_change_hash() {
+        let a = StandardMaterial {
+            parallax_mapping_method: ParallaxMappingMethod::Relief { max_steps: 4 },
+            ..default()
+        };
+        let b = StandardMaterial {
+            parallax_mapping_method: ParallaxMappingMethod::Relief { max_steps: 8 },
+            ..default()
+        };
+        assert_ne!(a.generate_hash(), b.generate_hash());
     }
 }
@@ -0,0 +1,46 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_hidden_alley_scene::SCENE_SETTLE_FRAMES;
+
+use crate::{Args, BenchmarkFinished, StartBenchmark};
+
+/// With `--bench-budget-ms`, starts the benchmark once the scene has loaded, then prints whether
+/// the frame time was within budget and exits, with an error if it wasn't.
+pub fn run_bench_budget(
+    mut settled_frames: Local<u32>,
+    args: Res<Args>,
+    mut start: EventWriter<StartBenchmark>,
+    mut finished: EventReader<BenchmarkFinished>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(budget) = args.bench_budget_ms else {
+        return;
+    };
+    if let Some(result) = finished.read().last() {
+        let (metric, measured) = if args.bench_budget_p99 {
+            ("p99", result.p99_frame_time_ms)
+        } else {
+            ("avg", result.avg_frame_time_ms)
+        };
+        let passed = measured <= budget;
+        println!(
+            "Benchmark budget {}: {} frame time {:.2}ms, budget {:.2}ms",
+            if passed { "PASS" } else { "FAIL" },
+            metric,
+            measured,
+            budget
+        );
+        if !passed {
+            std::process::exit(1);
+        }
+        exit.send(AppExit);
+        return;
+    }
+
+    if *settled_frames > SCENE_SETTLE_FRAMES {
+        return;
+    }
+    *settled_frames += 1;
+    if *settled_frames > SCENE_SETTLE_FRAMES {
+        start.send(StartBenchmark);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Written to this file after every benchmark run.
+pub const BENCHMARK_RESULTS_PATH: &str = "benchmark_results.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct BenchmarkResults {
+    pub passes: usize,
+    pub avg_frame_time_ms: f32,
+    /// Zero in results saved before it was recorded
+    #[serde(default)]
+    pub p99_frame_time_ms: f32,
+    /// Average cpu frame time at each camera position, across all passes
+    pub step_frame_times_ms: Vec<f32>,
+}
+
+impl BenchmarkResults {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Prints the change of each step against the baseline. Returns false if any step, or the
+    /// overall average, got slower by more than `tolerance` percent.
+    pub fn compare(&self, baseline: &BenchmarkResults, tolerance: f32) -> bool {
+        let mut passed = true;
+        let mut print_change = |label: String, current: f32, baseline: f32| {
+            let change = (current - baseline) / baseline * 100.0;
+            let regressed = change > tolerance;
+            passed &= !regressed;
+            println!(
+                "{}: {:.2}ms vs {:.2}ms baseline ({:+.1}%){}",
+                label,
+                current,
+                baseline,
+                change,
+                if regressed { " REGRESSION" } else { "" }
+            );
+        };
+        for (i, (current, baseline)) in self
+            .step_frame_times_ms
+            .iter()
+            .zip(&baseline.step_frame_times_ms)
+            .enumerate()
+        {
+            print_change(format!("Step {}", i + 1), *current, *baseline);
+        }
+        print_change(
+            String::from("Average"),
+            self.avg_frame_time_ms,
+            baseline.avg_frame_time_ms,
+        );
+        if self.step_frame_times_ms.len() != baseline.step_frame_times_ms.len() {
+            println!(
+                "Baseline has {} steps, this run has {}",
+                baseline.step_frame_times_ms.len(),
+                self.step_frame_times_ms.len()
+            );
+        }
+        passed
+    }
+}
@@ -3,6 +3,7 @@
 use bevy::{
     input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
     prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
 };
 
 /// Provides basic movement functionality to the attached camera
@@ -18,16 +19,30 @@ pub struct CameraController {
     pub key_up: KeyCode,
     pub key_down: KeyCode,
     pub key_run: KeyCode,
+    pub key_roll_left: KeyCode,
+    pub key_roll_right: KeyCode,
+    pub key_roll_reset: KeyCode,
     pub mouse_key_enable_mouse: MouseButton,
+    /// Drag with this held to pan the orbit focus in orbit mode
+    pub mouse_key_pan: MouseButton,
     pub keyboard_key_enable_mouse: KeyCode,
+    /// Grabs and hides the cursor for mouse look until pressed again or Escape
+    pub cursor_grab_toggle: KeyCode,
     pub walk_speed: f32,
     pub run_speed: f32,
     pub friction: f32,
     pub pitch: f32,
     pub yaw: f32,
+    pub roll: f32,
+    /// Radians per second
+    pub roll_speed: f32,
     pub velocity: Vec3,
+    /// Whether mouse movement currently turns the camera, updated every frame
+    pub mouse_look: bool,
     pub orbit_focus: Vec3,
     pub orbit_mode: bool,
+    /// Fraction of the distance to the orbit focus panned per pixel dragged
+    pub pan_sensitivity: f32,
     pub scroll_wheel_speed: f32,
     pub lock_y: bool,
 }
@@ -46,7 +61,10 @@ impl CameraController {
     {:?} - Up
     {:?} - Down
     {:?} - Run
+    {:?}/{:?}/{:?} - RollLeft/RollRight/ResetRoll
     {:?}/{:?} - EnableMouse
+    {:?} - Pan (orbit mode)
+    {:?}/Escape - GrabCursor
 ",
             self.key_forward,
             self.key_back,
@@ -55,8 +73,13 @@ impl CameraController {
             self.key_up,
             self.key_down,
             self.key_run,
+            self.key_roll_left,
+            self.key_roll_right,
+            self.key_roll_reset,
             self.mouse_key_enable_mouse,
             self.keyboard_key_enable_mouse,
+            self.mouse_key_pan,
+            self.cursor_grab_toggle,
         );
         self
     }
@@ -75,22 +98,32 @@ impl Default for CameraController {
             key_up: KeyCode::KeyE,
             key_down: KeyCode::KeyQ,
             key_run: KeyCode::ShiftLeft,
+            key_roll_left: KeyCode::KeyZ,
+            key_roll_right: KeyCode::KeyC,
+            key_roll_reset: KeyCode::KeyR,
             mouse_key_enable_mouse: MouseButton::Left,
+            mouse_key_pan: MouseButton::Middle,
             keyboard_key_enable_mouse: KeyCode::KeyM,
+            cursor_grab_toggle: KeyCode::KeyG,
             walk_speed: 5.0,
             run_speed: 15.0,
             friction: 0.5,
             pitch: 0.0,
             yaw: 0.0,
+            roll: 0.0,
+            roll_speed: 0.5,
             velocity: Vec3::ZERO,
+            mouse_look: false,
             orbit_focus: Vec3::ZERO,
             orbit_mode: false,
+            pan_sensitivity: 0.002,
             scroll_wheel_speed: 0.1,
             lock_y: false,
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn camera_controller(
     time: Res<Time>,
     mut mouse_events: EventReader<MouseMotion>,
@@ -98,6 +131,8 @@ pub fn camera_controller(
     mut scroll_evr: EventReader<MouseWheel>,
     key_input: Res<ButtonInput<KeyCode>>,
     mut move_toggled: Local<bool>,
+    mut cursor_grabbed: Local<bool>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
     mut query: Query<(&mut Transform, &mut CameraController), With<Camera>>,
 ) {
     let dt = time.delta_seconds();
@@ -148,6 +183,22 @@ pub fn camera_controller(
         if key_input.just_pressed(options.keyboard_key_enable_mouse) {
             *move_toggled = !*move_toggled;
         }
+        let grab = if key_input.just_pressed(options.cursor_grab_toggle) {
+            Some(!*cursor_grabbed)
+        } else if key_input.just_pressed(KeyCode::Escape) && *cursor_grabbed {
+            Some(false)
+        } else {
+            None
+        };
+        if let (Some(grab), Ok(mut window)) = (grab, windows.get_single_mut()) {
+            *cursor_grabbed = grab;
+            window.cursor.grab_mode = if grab {
+                CursorGrabMode::Locked
+            } else {
+                CursorGrabMode::None
+            };
+            window.cursor.visible = !grab;
+        }
 
         // Apply movement update
         if axis_input != Vec3::ZERO {
@@ -183,8 +234,19 @@ pub fn camera_controller(
         options.orbit_focus += translation_delta;
 
         // Handle mouse input
+        options.mouse_look = mouse_button_input.pressed(options.mouse_key_enable_mouse)
+            || *move_toggled
+            || *cursor_grabbed;
         let mut mouse_delta = Vec2::ZERO;
-        if mouse_button_input.pressed(options.mouse_key_enable_mouse) || *move_toggled {
+        if options.orbit_mode && mouse_button_input.pressed(options.mouse_key_pan) {
+            let pan_delta: Vec2 = mouse_events.read().map(|event| event.delta).sum();
+            // Move the focus and camera together in the view plane so the view doesn't rotate
+            let pan = (pan_delta.y * *transform.up() - pan_delta.x * *transform.right())
+                * transform.translation.distance(options.orbit_focus)
+                * options.pan_sensitivity;
+            transform.translation += pan;
+            options.orbit_focus += pan;
+        } else if options.mouse_look {
             for mouse_event in mouse_events.read() {
                 mouse_delta += mouse_event.delta;
             }
@@ -192,7 +254,21 @@ pub fn camera_controller(
             mouse_events.clear();
         }
 
-        if mouse_delta != Vec2::ZERO {
+        let mut roll_changed = false;
+        if key_input.pressed(options.key_roll_left) {
+            options.roll += options.roll_speed * dt;
+            roll_changed = true;
+        }
+        if key_input.pressed(options.key_roll_right) {
+            options.roll -= options.roll_speed * dt;
+            roll_changed = true;
+        }
+        if key_input.just_pressed(options.key_roll_reset) {
+            options.roll = 0.0;
+            roll_changed = true;
+        }
+
+        if mouse_delta != Vec2::ZERO || roll_changed {
             let sensitivity = if options.orbit_mode {
                 options.sensitivity * 2.0
             } else {
@@ -207,7 +283,8 @@ pub fn camera_controller(
             );
 
             // Apply look update
-            transform.rotation = Quat::from_euler(EulerRot::ZYX, 0.0, yaw, pitch);
+            // Roll is applied last so it turns around the camera's own forward axis
+            transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, options.roll);
             options.pitch = pitch;
             options.yaw = yaw;
 
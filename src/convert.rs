@@ -1,60 +1,268 @@
+use bevy::log::debug;
 use threadpool::ThreadPool;
 
-use std::{fs, io::Write, process::Command, thread::available_parallelism};
-
-pub fn change_gltf_to_use_ktx2() {
-    for path in ["./assets/hidden_alley/ph_hidden_alley_bevy_bake.gltf"] {
-        let contents = fs::read_to_string(path).unwrap();
-        let new = contents
-            .replace("\"mimeType\":\"image/jpeg\",", "")
-            .replace(".jpg", ".ktx2");
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(path)
-            .unwrap();
-        let _ = file.write(new.as_bytes()).unwrap();
-    }
-}
-
-pub fn convert_images_to_ktx2() {
-    for path in ["./assets/hidden_alley/"] {
-        let pool = ThreadPool::new(available_parallelism().unwrap().get());
-        for path in fs::read_dir(path).unwrap() {
-            pool.execute(move || {
-                if let Ok(path) = path {
-                    let path = path.path();
-                    if path.is_file() && path.extension().unwrap() == "png" {
-                        let path_string = path.to_string_lossy().to_string();
-                        let new_path_string =
-                            path.with_extension("ktx2").to_string_lossy().to_string();
-                        let name = path.file_stem().unwrap().to_string_lossy().to_lowercase();
-                        let nor = name.contains("Nor");
-
-                        let mut cmd = Command::new("kram");
-                        cmd.arg("encode").arg("-f");
-                        // should be able to use bc5 for nor and rough+metal, but they looked bad
-                        cmd.arg("bc7");
-                        if nor {
-                            cmd.arg("-normal");
-                        }
-                        cmd.arg("-type")
-                            .arg("2d")
-                            .arg("-mipmin")
-                            .arg("1")
-                            .arg("-srgb")
-                            .arg("-zstd")
-                            .arg("0")
-                            .arg("-i")
-                            .arg(path_string)
-                            .arg("-o")
-                            .arg(new_path_string);
-                        dbg!(&cmd);
-                        cmd.output().expect("ls command failed to start");
-                    }
-                }
-            });
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::available_parallelism,
+};
+
+/// GPU format of the converted ktx2 images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// Desktop, encoded with kram
+    Bc7,
+    /// Mobile, encoded with kram
+    Astc,
+    /// Older mobile, encoded with kram
+    Etc2,
+}
+
+impl FromStr for ConvertFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bc7" => Ok(ConvertFormat::Bc7),
+            "astc" => Ok(ConvertFormat::Astc),
+            "etc2" => Ok(ConvertFormat::Etc2),
+            "uastc" => Err(
+                "uastc isn't supported, loading Basis supercompressed ktx2 needs \
+                bevy's basis-universal feature, which isn't enabled. Use bc7, astc or etc2"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "Unsupported convert format {s}, expected one of bc7, astc, etc2"
+            )),
         }
-        pool.join();
     }
 }
+
+impl ConvertFormat {
+    fn command(self, input: String, output: String, normal: bool) -> Command {
+        let kram_format = match self {
+            ConvertFormat::Bc7 => "bc7",
+            ConvertFormat::Astc => "astc4x4",
+            ConvertFormat::Etc2 => "etc2rgba",
+        };
+        let mut cmd = Command::new("kram");
+        cmd.arg("encode").arg("-f");
+        // should be able to use bc5 for nor and rough+metal, but they looked bad
+        cmd.arg(kram_format);
+        if normal {
+            cmd.arg("-normal");
+        }
+        cmd.arg("-type")
+            .arg("2d")
+            .arg("-mipmin")
+            .arg("1")
+            .arg("-srgb")
+            .arg("-zstd")
+            .arg("0")
+            .arg("-i")
+            .arg(input)
+            .arg("-o")
+            .arg(output);
+        cmd
+    }
+}
+
+/// The gltf files to convert: `scene` plus any `extra` paths, all relative to the assets folder.
+/// A directory in `extra` adds every .gltf file in it.
+pub fn gltf_paths(scene: &str, extra: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for path in std::iter::once(scene).chain(extra.iter().map(String::as_str)) {
+        let path = Path::new("./assets").join(path);
+        if path.is_dir() {
+            let mut files = fs::read_dir(&path)
+                .unwrap()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "gltf"))
+                .collect::<Vec<_>>();
+            files.sort();
+            paths.extend(files);
+        } else {
+            paths.push(path);
+        }
+    }
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(fs::canonicalize(path).unwrap_or(path.clone())));
+    paths
+}
+
+/// Only .gltf files with external images can be rewritten, the images in a .glb are embedded
+/// in its binary chunk. Returns whether the file was rewritten.
+pub fn change_gltf_to_use_ktx2(path: &Path) -> bool {
+    if is_glb(path) {
+        eprintln!(
+            "Can't convert {} to use ktx2, images embedded in .glb aren't supported",
+            path.display()
+        );
+        return false;
+    }
+    let contents = fs::read_to_string(path).unwrap();
+    let new = contents
+        .replace("\"mimeType\":\"image/jpeg\",", "")
+        .replace(".jpg", ".ktx2");
+    if new == contents {
+        return false;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .unwrap();
+    let _ = file.write(new.as_bytes()).unwrap();
+    true
+}
+
+/// The png images referenced by `gltf`, and whether each is used as a material's normal map. A
+/// referenced .jpg is converted from the png next to it.
+fn referenced_images(gltf: &Path) -> Vec<(PathBuf, bool)> {
+    let Ok(json) = fs::read_to_string(gltf)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(serde_json::from_str::<serde_json::Value>(&contents)?))
+    else {
+        eprintln!("Failed to read {}", gltf.display());
+        return Vec::new();
+    };
+    let dir = gltf.parent().unwrap();
+    let normal_maps = json["materials"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|material| material["normalTexture"]["index"].as_u64())
+        .filter_map(|texture| json["textures"][texture as usize]["source"].as_u64())
+        .collect::<HashSet<_>>();
+    json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(i, image)| Some((image["uri"].as_str()?, normal_maps.contains(&(i as u64)))))
+        .filter(|(uri, _)| !uri.starts_with("data:"))
+        .map(|(uri, normal)| (dir.join(uri).with_extension("png"), normal))
+        .filter(|(path, _)| path.is_file())
+        .collect()
+}
+
+/// The ktx2 images `scene` references that don't exist, and how many ktx2 images it references.
+pub fn missing_ktx2_images(scene: &str) -> (Vec<String>, usize) {
+    let path = Path::new("./assets").join(scene);
+    let Ok(json) = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(serde_json::from_str::<serde_json::Value>(&contents)?))
+    else {
+        return (Vec::new(), 0);
+    };
+    let dir = path.parent().unwrap();
+    let ktx2 = json["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["uri"].as_str())
+        .filter(|uri| uri.ends_with(".ktx2"))
+        .collect::<Vec<_>>();
+    let missing = ktx2
+        .iter()
+        .filter(|uri| !dir.join(uri).is_file())
+        .map(|uri| uri.to_string())
+        .collect();
+    (missing, ktx2.len())
+}
+
+/// Converts the png images referenced by any of `gltfs` to ktx2. Images shared between files
+/// are only converted once. Returns false if any image failed to convert.
+pub fn convert_images_to_ktx2(gltfs: &[PathBuf], format: ConvertFormat) -> bool {
+    // Whether each image is a normal map in any of the files
+    let mut images = BTreeMap::new();
+    for gltf in gltfs.iter().filter(|gltf| !is_glb(gltf)) {
+        for (image, normal) in referenced_images(gltf) {
+            *images
+                .entry(fs::canonicalize(&image).unwrap_or(image))
+                .or_default() |= normal;
+        }
+    }
+    let failed = Arc::new(AtomicUsize::new(0));
+    let pool = ThreadPool::new(available_parallelism().unwrap().get());
+    for (path, normal) in &images {
+        let (path, normal, failed) = (path.clone(), *normal, failed.clone());
+        pool.execute(move || {
+            let path_string = path.to_string_lossy().to_string();
+            let new_path_string = path.with_extension("ktx2").to_string_lossy().to_string();
+
+            let mut cmd = format.command(path_string, new_path_string, normal);
+            debug!("{cmd:?}");
+            match cmd.output() {
+                Ok(output) if output.status.success() => return,
+                Ok(output) => eprintln!(
+                    "kram failed to convert {} ({}):\n{}",
+                    path.display(),
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+                Err(e) => eprintln!("Failed to run kram, is it installed and on the PATH? {e}"),
+            }
+            failed.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    pool.join();
+    let failed = failed.load(Ordering::Relaxed);
+    if failed > 0 {
+        eprintln!("Failed to convert {failed} of {} images", images.len());
+        return false;
+    }
+    println!("Converted {} images", images.len());
+    report_texture_memory(images.keys());
+    true
+}
+
+/// Every supported format uses 128 bits per 4x4 block.
+const COMPRESSED_BYTES_PER_PIXEL: f64 = 1.0;
+/// A full mip chain adds a third on top of the base level.
+const MIP_CHAIN_FACTOR: f64 = 4.0 / 3.0;
+
+/// Prints the file sizes of the converted png `images` and their ktx2 conversions, along with
+/// an estimate of the GPU memory they need with mipmaps.
+fn report_texture_memory<'a>(images: impl Iterator<Item = &'a PathBuf>) {
+    let (mut source_bytes, mut ktx2_bytes, mut source_gpu, mut ktx2_gpu) = (0, 0, 0.0, 0.0);
+    for path in images {
+        let (Ok(source), Ok(ktx2), Ok((width, height))) = (
+            fs::metadata(path),
+            fs::metadata(path.with_extension("ktx2")),
+            image::image_dimensions(path),
+        ) else {
+            continue;
+        };
+        let pixels = width as f64 * height as f64 * MIP_CHAIN_FACTOR;
+        source_bytes += source.len();
+        ktx2_bytes += ktx2.len();
+        source_gpu += pixels * 4.0;
+        ktx2_gpu += pixels * COMPRESSED_BYTES_PER_PIXEL;
+    }
+    let mb = |bytes: f64| bytes / (1024.0 * 1024.0);
+    println!(
+        "Texture files: {:.1}MB png, {:.1}MB ktx2",
+        mb(source_bytes as f64),
+        mb(ktx2_bytes as f64)
+    );
+    println!(
+        "Estimated GPU memory: {:.1}MB uncompressed, {:.1}MB compressed",
+        mb(source_gpu),
+        mb(ktx2_gpu)
+    );
+}
+
+fn is_glb(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("glb"))
+}
@@ -0,0 +1,328 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        primitives::Aabb,
+        render_resource::{AsBindGroup, RenderPipelineDescriptor, SpecializedMeshPipelineError},
+    },
+    utils::{HashMap, HashSet},
+};
+
+use bevy_hidden_alley_scene::WasInstanced;
+
+use crate::{BenchPositions, GrifLight};
+
+/// State saved while the AO debug view is active so it can be restored on toggle-off.
+#[derive(Resource, Default)]
+pub struct AoDebugView {
+    enabled: bool,
+    materials: HashMap<AssetId<StandardMaterial>, StandardMaterial>,
+    lights: HashMap<Entity, Visibility>,
+}
+
+/// Toggles a view where all materials are flat white and the `GrifLight`s are hidden, leaving
+/// only the environment light so the SSAO contribution is easy to see.
+/// Materials are kept lit rather than made unlit, since unlit materials skip SSAO entirely.
+pub fn toggle_ao_debug_view(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut view: ResMut<AoDebugView>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut lights: Query<(Entity, &mut Visibility), With<GrifLight>>,
+) {
+    if !keys.just_pressed(KeyCode::F1) {
+        return;
+    }
+    view.enabled = !view.enabled;
+    if view.enabled {
+        for (id, mat) in materials.iter_mut() {
+            view.materials.insert(id, mat.clone());
+            mat.base_color = Color::WHITE;
+            // Keep the texture for masked materials so foliage cutouts still work
+            if !matches!(mat.alpha_mode, AlphaMode::Mask(_)) {
+                mat.base_color_texture = None;
+            }
+            mat.emissive = Color::BLACK;
+            mat.emissive_texture = None;
+            mat.metallic = 0.0;
+            mat.perceptual_roughness = 1.0;
+            mat.metallic_roughness_texture = None;
+        }
+        for (entity, mut visibility) in &mut lights {
+            view.lights.insert(entity, *visibility);
+            *visibility = Visibility::Hidden;
+        }
+        info!("AO debug view enabled");
+    } else {
+        for (id, original) in view.materials.drain() {
+            if let Some(mat) = materials.get_mut(id) {
+                *mat = original;
+            }
+        }
+        for (entity, visibility) in view.lights.drain() {
+            if let Ok((_, mut current)) = lights.get_mut(entity) {
+                *current = visibility;
+            }
+        }
+        info!("AO debug view disabled");
+    }
+}
+
+pub type CascadeDebugMaterial = ExtendedMaterial<StandardMaterial, CascadeDebug>;
+
+/// Material extension that turns on bevy's built in shadow cascade coloring in the pbr shader.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+pub struct CascadeDebug {}
+
+impl MaterialExtension for CascadeDebug {
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment
+                .shader_defs
+                .push("DIRECTIONAL_LIGHT_SHADOW_MAP_DEBUG_CASCADES".into());
+        }
+        Ok(())
+    }
+}
+
+/// The original material of an entity that is currently using a [`CascadeDebugMaterial`].
+#[derive(Component)]
+pub struct CascadeDebugOriginal(Handle<StandardMaterial>);
+
+/// Toggles tinting the scene by which directional light shadow cascade each fragment falls into.
+/// Requires `MaterialPlugin::<CascadeDebugMaterial>`.
+pub fn toggle_cascade_debug_view(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut enabled: Local<bool>,
+    std_entities: Query<(Entity, &Handle<StandardMaterial>)>,
+    debug_entities: Query<(Entity, &CascadeDebugOriginal)>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut debug_materials: ResMut<Assets<CascadeDebugMaterial>>,
+) {
+    if !keys.just_pressed(KeyCode::F2) {
+        return;
+    }
+    *enabled = !*enabled;
+    if *enabled {
+        let mut debug_handles = HashMap::new();
+        for (entity, mat_h) in &std_entities {
+            let Some(mat) = materials.get(mat_h) else {
+                continue;
+            };
+            let debug_h = debug_handles
+                .entry(mat_h.id())
+                .or_insert_with(|| {
+                    debug_materials.add(CascadeDebugMaterial {
+                        base: mat.clone(),
+                        extension: CascadeDebug {},
+                    })
+                })
+                .clone();
+            commands
+                .entity(entity)
+                .remove::<Handle<StandardMaterial>>()
+                .insert((debug_h, CascadeDebugOriginal(mat_h.clone())));
+        }
+        info!("Cascade debug view enabled");
+    } else {
+        for (entity, original) in &debug_entities {
+            commands
+                .entity(entity)
+                .remove::<(Handle<CascadeDebugMaterial>, CascadeDebugOriginal)>()
+                .insert(original.0.clone());
+        }
+        info!("Cascade debug view disabled");
+    }
+}
+
+/// The entities other meshes were merged onto. They keep their own mesh, but it's shared, so
+/// they count as instanced too.
+fn instancing_canonicals<'a>(
+    instanced: impl Iterator<Item = Option<&'a WasInstanced<Mesh>>>,
+) -> HashSet<Entity> {
+    instanced
+        .filter_map(|instanced| Some(instanced?.canonical))
+        .collect()
+}
+
+/// Press F3 to outline meshes that were collapsed onto a shared mesh by the auto instancing in
+/// green, and meshes that stayed unique in red. The canonical entity a group was merged onto
+/// counts as instanced. Requires `--instance`.
+#[allow(clippy::type_complexity)]
+pub fn draw_instancing_debug_view(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut enabled: Local<bool>,
+    mut gizmos: Gizmos,
+    entities: Query<
+        (
+            Entity,
+            &Aabb,
+            &GlobalTransform,
+            &ViewVisibility,
+            Option<&WasInstanced<Mesh>>,
+        ),
+        With<Handle<Mesh>>,
+    >,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        *enabled = !*enabled;
+        info!(
+            "Instancing debug view {}",
+            if *enabled { "enabled" } else { "disabled" }
+        );
+    }
+    if !*enabled {
+        return;
+    }
+    let canonicals = instancing_canonicals(entities.iter().map(|(.., instanced)| instanced));
+    for (entity, aabb, transform, visibility, instanced) in &entities {
+        if !visibility.get() {
+            continue;
+        }
+        let instanced = instanced.is_some() || canonicals.contains(&entity);
+        let bounds = Transform::from_translation(aabb.center.into())
+            .with_scale(Vec3::from(aabb.half_extents) * 2.0);
+        let color = if instanced { Color::GREEN } else { Color::RED };
+        gizmos.cuboid(transform.mul_transform(bounds), color);
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum InstancingFilter {
+    #[default]
+    All,
+    InstancedOnly,
+    UniqueOnly,
+}
+
+/// Mesh visibilities saved while the instancing filter hides some of them.
+#[derive(Default)]
+pub struct InstancingFilterView {
+    filter: InstancingFilter,
+    hidden: HashMap<Entity, Visibility>,
+}
+
+/// Press F4 to cycle between showing every mesh, only the meshes that share their mesh with
+/// others through auto instancing, and only the ones that stayed unique. The canonical entity
+/// a group was merged onto counts as instanced. Requires `--debug-instancing`.
+#[allow(clippy::type_complexity)]
+pub fn cycle_instancing_filter(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut view: Local<InstancingFilterView>,
+    mut entities: Query<(Entity, &mut Visibility, Option<&WasInstanced<Mesh>>), With<Handle<Mesh>>>,
+) {
+    if !keys.just_pressed(KeyCode::F4) {
+        return;
+    }
+    for (entity, visibility) in view.hidden.drain() {
+        if let Ok((_, mut current, _)) = entities.get_mut(entity) {
+            *current = visibility;
+        }
+    }
+    view.filter = match view.filter {
+        InstancingFilter::All => InstancingFilter::InstancedOnly,
+        InstancingFilter::InstancedOnly => InstancingFilter::UniqueOnly,
+        InstancingFilter::UniqueOnly => InstancingFilter::All,
+    };
+    info!("Instancing filter: {:?}", view.filter);
+    if view.filter == InstancingFilter::All {
+        return;
+    }
+
+    let canonicals = instancing_canonicals(entities.iter().map(|(.., instanced)| instanced));
+    let show_instanced = view.filter == InstancingFilter::InstancedOnly;
+    for (entity, mut visibility, instanced) in &mut entities {
+        let is_instanced = instanced.is_some() || canonicals.contains(&entity);
+        if is_instanced != show_instanced {
+            view.hidden.insert(entity, *visibility);
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// With `--debug-bench-path`, draws the benchmark camera positions as spheres with a ray in their
+/// view direction, connected in the order the benchmark visits them.
+pub fn draw_bench_path(mut gizmos: Gizmos, positions: Res<BenchPositions>) {
+    for (i, transform) in positions.0.iter().enumerate() {
+        let color = Color::hsl(i as f32 * 360.0 / positions.0.len() as f32, 0.8, 0.6);
+        gizmos.sphere(transform.translation, Quat::IDENTITY, 0.15, color);
+        gizmos.ray(transform.translation, *transform.forward(), color);
+    }
+    gizmos.linestrip(
+        positions.0.iter().map(|transform| transform.translation),
+        Color::WHITE,
+    );
+}
+
+/// Grid lines drawn on each side of the origin are capped at this, so a tiny `--grid-spacing`
+/// can't stall the frame.
+const MAX_GRID_LINES: i32 = 500;
+
+/// The `--grid` gizmo on the XZ plane.
+#[derive(Resource)]
+pub struct GridGizmo {
+    pub spacing: f32,
+    /// Distance from the origin the grid reaches along X and Z
+    pub extent: f32,
+    pub visible: bool,
+}
+
+/// Press F6 to show or hide the `--grid` gizmo.
+pub fn toggle_grid(keys: Res<ButtonInput<KeyCode>>, mut grid: ResMut<GridGizmo>) {
+    if !keys.just_pressed(KeyCode::F6) {
+        return;
+    }
+    grid.visible = !grid.visible;
+    info!("Grid: {}", grid.visible);
+}
+
+/// Draws grid lines every `spacing` on the XZ plane, with the X axis in red and the Z axis in
+/// blue, for a sense of scale and direction away from the alley.
+pub fn draw_grid(mut gizmos: Gizmos, grid: Res<GridGizmo>) {
+    if !grid.visible || grid.spacing <= 0.0 {
+        return;
+    }
+    let lines = ((grid.extent / grid.spacing) as i32).min(MAX_GRID_LINES);
+    let extent = lines as f32 * grid.spacing;
+    let color = Color::rgba(0.5, 0.5, 0.5, 0.6);
+    for i in -lines..=lines {
+        let offset = i as f32 * grid.spacing;
+        let (x_color, z_color) = if i == 0 {
+            (Color::RED, Color::BLUE)
+        } else {
+            (color, color)
+        };
+        gizmos.line(
+            Vec3::new(-extent, 0.0, offset),
+            Vec3::new(extent, 0.0, offset),
+            x_color,
+        );
+        gizmos.line(
+            Vec3::new(offset, 0.0, -extent),
+            Vec3::new(offset, 0.0, extent),
+            z_color,
+        );
+    }
+}
+
+const TIME_SCALES: [(KeyCode, f32); 3] = [
+    (KeyCode::Digit8, 0.25),
+    (KeyCode::Digit9, 1.0),
+    (KeyCode::Digit0, 4.0),
+];
+
+/// Press 8/9/0 to run virtual time at 0.25x/1x/4x. The benchmark times itself with real time,
+/// so its measurements aren't affected.
+pub fn set_time_scale(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    let Some((_, scale)) = TIME_SCALES.iter().find(|(key, _)| keys.just_pressed(*key)) else {
+        return;
+    };
+    time.set_relative_speed(*scale);
+    info!("Time scale: {scale}x");
+}
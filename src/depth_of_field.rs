@@ -0,0 +1,81 @@
+//! Depth of field isn't in bevy 0.13, so this module is only built with the `bevy_main` feature.
+
+use bevy::{core_pipeline::dof::DepthOfFieldSettings, prelude::*, render::primitives::Aabb};
+
+use crate::{raycast::raycast_aabbs, BenchmarkCamera};
+
+pub fn dof_settings() -> DepthOfFieldSettings {
+    DepthOfFieldSettings {
+        focal_distance: 4.0,
+        aperture_f_stops: 2.8,
+        ..default()
+    }
+}
+
+/// Press ArrowUp/ArrowDown to move the focal distance, ArrowLeft/ArrowRight to open/close the
+/// aperture by a third of a stop.
+pub fn adjust_depth_of_field(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut cameras: Query<&mut DepthOfFieldSettings, With<BenchmarkCamera>>,
+) {
+    let focal_factor = if keys.just_pressed(KeyCode::ArrowUp) {
+        1.25
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        1.0 / 1.25
+    } else {
+        1.0
+    };
+    let stop_factor = 2.0f32.sqrt().powf(1.0 / 3.0);
+    let aperture_factor = if keys.just_pressed(KeyCode::ArrowRight) {
+        stop_factor
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        1.0 / stop_factor
+    } else {
+        1.0
+    };
+    if focal_factor == 1.0 && aperture_factor == 1.0 {
+        return;
+    }
+    for mut dof in &mut cameras {
+        dof.focal_distance *= focal_factor;
+        dof.aperture_f_stops *= aperture_factor;
+        info!(
+            "Depth of field focal distance: {:.2}m, aperture: f/{:.1}",
+            dof.focal_distance, dof.aperture_f_stops
+        );
+    }
+}
+
+/// Press F to toggle auto-focus, which keeps the focal distance on whatever is at the center of
+/// the screen. The distance comes from a ray against the mesh bounding boxes so it's approximate.
+#[allow(clippy::type_complexity)]
+pub fn auto_focus(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut enabled: Local<bool>,
+    mut cameras: Query<(&GlobalTransform, &mut DepthOfFieldSettings), With<BenchmarkCamera>>,
+    meshes: Query<(Entity, &Aabb, &GlobalTransform, &ViewVisibility), With<Handle<Mesh>>>,
+) {
+    if keys.just_pressed(KeyCode::KeyF) {
+        *enabled = !*enabled;
+        info!(
+            "Depth of field auto-focus {}",
+            if *enabled { "enabled" } else { "disabled" }
+        );
+    }
+    if !*enabled {
+        return;
+    }
+    for (camera_transform, mut dof) in &mut cameras {
+        let ray = Ray3d {
+            origin: camera_transform.translation(),
+            direction: camera_transform.forward(),
+        };
+        let visible = meshes
+            .iter()
+            .filter(|(.., visibility)| visibility.get())
+            .map(|(entity, aabb, transform, _)| (entity, aabb, transform));
+        if let Some((_, distance)) = raycast_aabbs(ray, visible) {
+            dof.focal_distance = distance;
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::{asset::LoadState, prelude::*, utils::HashSet};
+use futures_lite::future;
+
+use crate::{convert::missing_ktx2_images, Args};
+
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    pub diffuse_map: Handle<Image>,
+    pub specular_map: Handle<Image>,
+    pub intensity: f32,
+}
+
+/// Two environment maps the camera's `EnvironmentMapLight` is cross-faded between.
+/// Only one map can be bound at a time, so the intensity is interpolated and the maps are
+/// swapped when `blend` crosses 0.5.
+#[derive(Resource)]
+pub struct EnvironmentMaps {
+    pub a: EnvironmentMap,
+    pub b: EnvironmentMap,
+    pub blend: f32,
+}
+
+impl EnvironmentMaps {
+    /// Loads map `a` from `--env-diffuse`/`--env-specular` and map `b` from the `-b` variants,
+    /// falling back to map `a` for anything not given.
+    pub fn from_args(args: &Args, asset_server: &AssetServer) -> Self {
+        let a = EnvironmentMap {
+            diffuse_map: asset_server.load(args.env_diffuse.clone()),
+            specular_map: asset_server.load(args.env_specular.clone()),
+            intensity: args.env_intensity,
+        };
+        let b = EnvironmentMap {
+            diffuse_map: args
+                .env_diffuse_b
+                .as_ref()
+                .map(|path| asset_server.load(path.clone()))
+                .unwrap_or_else(|| a.diffuse_map.clone()),
+            specular_map: args
+                .env_specular_b
+                .as_ref()
+                .map(|path| asset_server.load(path.clone()))
+                .unwrap_or_else(|| a.specular_map.clone()),
+            intensity: args.env_intensity_b.unwrap_or(a.intensity),
+        };
+        EnvironmentMaps { a, b, blend: 0.0 }
+    }
+
+    pub fn light(&self) -> EnvironmentMapLight {
+        let active = if self.blend < 0.5 { &self.a } else { &self.b };
+        EnvironmentMapLight {
+            diffuse_map: active.diffuse_map.clone(),
+            specular_map: active.specular_map.clone(),
+            intensity: self.a.intensity + (self.b.intensity - self.a.intensity) * self.blend,
+        }
+    }
+}
+
+/// Press ; and ' to blend towards environment map a or b.
+pub fn blend_environment_maps(keys: Res<ButtonInput<KeyCode>>, mut maps: ResMut<EnvironmentMaps>) {
+    let step = if keys.just_pressed(KeyCode::Quote) {
+        0.1
+    } else if keys.just_pressed(KeyCode::Semicolon) {
+        -0.1
+    } else {
+        return;
+    };
+    maps.blend = (maps.blend + step).clamp(0.0, 1.0);
+    info!("Environment map blend: {:.1}", maps.blend);
+}
+
+/// Press ] and [ to raise/lower the intensity of both environment maps.
+pub fn adjust_environment_map_intensity(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut maps: ResMut<EnvironmentMaps>,
+) {
+    let factor = if keys.just_pressed(KeyCode::BracketRight) {
+        1.25
+    } else if keys.just_pressed(KeyCode::BracketLeft) {
+        1.0 / 1.25
+    } else {
+        return;
+    };
+    maps.a.intensity *= factor;
+    maps.b.intensity *= factor;
+    info!("Environment map intensity: {}", maps.light().intensity);
+}
+
+pub fn apply_environment_maps(
+    maps: Res<EnvironmentMaps>,
+    mut env_maps: Query<&mut EnvironmentMapLight, With<Camera>>,
+) {
+    if !maps.is_changed() {
+        return;
+    }
+    for mut env_map in &mut env_maps {
+        *env_map = maps.light();
+    }
+}
+
+/// Ambient brightness used instead of a missing environment map, in line with the default
+/// `--env-intensity`.
+const FALLBACK_AMBIENT_BRIGHTNESS: f32 = 1000.0;
+
+/// Logs an error for environment maps that fail to load, since otherwise the scene is just
+/// black, and turns on a neutral ambient light as a fallback if there isn't one already.
+pub fn check_environment_maps(
+    maps: Res<EnvironmentMaps>,
+    asset_server: Res<AssetServer>,
+    mut ambient: ResMut<AmbientLight>,
+    mut reported: Local<HashSet<AssetId<Image>>>,
+) {
+    let handles = [
+        &maps.a.diffuse_map,
+        &maps.a.specular_map,
+        &maps.b.diffuse_map,
+        &maps.b.specular_map,
+    ];
+    for handle in handles {
+        if asset_server.load_state(handle) != LoadState::Failed || !reported.insert(handle.id()) {
+            continue;
+        }
+        let path = asset_server
+            .get_path(handle)
+            .map(|path| path.to_string())
+            .unwrap_or_default();
+        error!(
+            "Failed to load environment map {path}, expected it at assets/{path}. \
+            Set a different path with --env-diffuse/--env-specular"
+        );
+        if ambient.brightness == 0.0 {
+            warn!("Falling back to a constant ambient light");
+            *ambient = AmbientLight {
+                color: Color::WHITE,
+                brightness: FALLBACK_AMBIENT_BRIGHTNESS,
+            };
+        }
+    }
+}
+
+/// Checks at startup that the ktx2 environment maps and scene textures can load, since
+/// otherwise the only symptom is a black render.
+pub fn check_ktx2_support(args: Res<Args>, asset_server: Res<AssetServer>) {
+    let (missing, scene_ktx2) = missing_ktx2_images(&args.scene);
+    let env_ktx2 = [&args.env_diffuse, &args.env_specular]
+        .into_iter()
+        .chain(&args.env_diffuse_b)
+        .chain(&args.env_specular_b)
+        .filter(|path| path.ends_with(".ktx2"))
+        .count();
+    if env_ktx2 + scene_ktx2 > 0
+        && future::block_on(asset_server.get_asset_loader_with_extension("ktx2")).is_err()
+    {
+        error!(
+            "This build can't load .ktx2 images, but {} environment maps and {} scene textures \
+            are ktx2. Enable bevy's ktx2 and zstd features, or use --env-diffuse/--env-specular \
+            and an unconverted scene",
+            env_ktx2, scene_ktx2
+        );
+    }
+    if !missing.is_empty() {
+        error!(
+            "{} of the {} ktx2 textures {} references are missing, e.g. {}. Run with --convert \
+            to generate them",
+            missing.len(),
+            scene_ktx2,
+            args.scene,
+            missing[0]
+        );
+    }
+}
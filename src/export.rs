@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{BuildHasherDefault, Hash},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Context};
+use bevy_hidden_alley_scene::stable_hash::StableHasher;
+use serde_json::Value;
+
+/// What a mesh primitive is compared by: its mode, material, morph targets, index bytes and the
+/// bytes of each attribute sorted by name.
+type PrimitiveKey = (
+    u64,
+    Option<u64>,
+    String,
+    Option<Vec<u8>>,
+    Vec<(String, Vec<u8>)>,
+);
+
+/// Writes a copy of the `scene` gltf to `output`, both relative to the assets folder, with
+/// duplicate meshes and materials collapsed onto one shared entry like `--instance` does at
+/// load time. Meshes only match when their vertex data is byte identical, so node transforms
+/// are kept as they are. The buffers aren't repacked, the output references the same .bin and
+/// images as the source and needs to be written next to it.
+pub fn export_optimized(scene: &str, output: &str) -> anyhow::Result<()> {
+    let path = Path::new("./assets").join(scene);
+    let output = Path::new("./assets").join(output);
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("glb"))
+        || output
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("glb"))
+    {
+        bail!("Only .gltf files with external buffers can be exported");
+    }
+    let dir = path.parent().unwrap();
+    if output.parent() != Some(dir) {
+        bail!(
+            "{} needs to be in {} so its buffer and image uris resolve",
+            output.display(),
+            dir.display()
+        );
+    }
+    let mut gltf: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let buffers = array(&gltf, "buffers")
+        .iter()
+        .map(|buffer| {
+            let uri = buffer["uri"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Buffers without a uri aren't supported"))?;
+            if uri.starts_with("data:") {
+                bail!("Embedded data uri buffers aren't supported");
+            }
+            fs::read(dir.join(uri)).with_context(|| format!("Failed to read buffer {uri}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let material_remap = dedup(array(&gltf, "materials"), |material| {
+        let mut material = material.clone();
+        if let Some(material) = material.as_object_mut() {
+            material.remove("name");
+        }
+        Ok(material.to_string())
+    })?;
+    for mesh in array_mut(&mut gltf, "meshes") {
+        for primitive in mesh["primitives"].as_array_mut().into_iter().flatten() {
+            if let Some(material) = primitive["material"].as_u64() {
+                primitive["material"] = material_remap[material as usize].into();
+            }
+        }
+    }
+
+    let mesh_remap = dedup(array(&gltf, "meshes"), |mesh| {
+        let primitives = mesh["primitives"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|primitive| {
+                let indices = primitive["indices"]
+                    .as_u64()
+                    .map(|indices| accessor_bytes(&gltf, &buffers, indices))
+                    .transpose()?;
+                let mut attributes = primitive["attributes"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, accessor)| {
+                        let accessor = accessor
+                            .as_u64()
+                            .ok_or_else(|| anyhow!("Invalid accessor for {name}"))?;
+                        Ok((name.clone(), accessor_bytes(&gltf, &buffers, accessor)?))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                attributes.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Ok((
+                    primitive["mode"].as_u64().unwrap_or(4),
+                    primitive["material"].as_u64(),
+                    primitive["targets"].to_string(),
+                    indices,
+                    attributes,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<PrimitiveKey>>>()?;
+        Ok((primitives, mesh["weights"].to_string()))
+    })?;
+    for node in array_mut(&mut gltf, "nodes") {
+        if let Some(mesh) = node["mesh"].as_u64() {
+            node["mesh"] = mesh_remap[mesh as usize].into();
+        }
+    }
+
+    let (meshes, materials) = (
+        array(&gltf, "meshes").len(),
+        array(&gltf, "materials").len(),
+    );
+    let mesh_index = compact(&mut gltf, "meshes", &mesh_remap);
+    let material_index = compact(&mut gltf, "materials", &material_remap);
+    for node in array_mut(&mut gltf, "nodes") {
+        if let Some(mesh) = node["mesh"].as_u64() {
+            node["mesh"] = mesh_index[mesh as usize].into();
+        }
+    }
+    for mesh in array_mut(&mut gltf, "meshes") {
+        for primitive in mesh["primitives"].as_array_mut().into_iter().flatten() {
+            if let Some(material) = primitive["material"].as_u64() {
+                primitive["material"] = material_index[material as usize].into();
+            }
+        }
+    }
+
+    fs::write(&output, serde_json::to_string(&gltf)?)?;
+    println!(
+        "Exported {}: {} meshes to {}, {} materials to {}",
+        output.display(),
+        meshes,
+        array(&gltf, "meshes").len(),
+        materials,
+        array(&gltf, "materials").len()
+    );
+    Ok(())
+}
+
+fn array<'a>(gltf: &'a Value, key: &str) -> &'a [Value] {
+    gltf[key].as_array().map_or(&[], |array| array.as_slice())
+}
+
+fn array_mut<'a>(gltf: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut Value> {
+    gltf.get_mut(key)
+        .and_then(|array| array.as_array_mut())
+        .into_iter()
+        .flatten()
+}
+
+/// Maps each item to the index of the first item with an equal key. Keys are compared in full,
+/// not just by hash, so a hash collision can't merge two different items.
+fn dedup<K: Hash + Eq>(
+    items: &[Value],
+    mut key: impl FnMut(&Value) -> anyhow::Result<K>,
+) -> anyhow::Result<Vec<usize>> {
+    let mut first = HashMap::<K, usize, BuildHasherDefault<StableHasher>>::default();
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| Ok(*first.entry(key(item)?).or_insert(i)))
+        .collect()
+}
+
+/// Removes the items of `gltf[key]` that were remapped onto another one, returning the new
+/// index of each kept item.
+fn compact(gltf: &mut Value, key: &str, remap: &[usize]) -> Vec<usize> {
+    let mut index = vec![0; remap.len()];
+    let mut kept = 0;
+    for (i, canonical) in remap.iter().enumerate() {
+        if *canonical == i {
+            index[i] = kept;
+            kept += 1;
+        }
+    }
+    if let Some(items) = gltf.get_mut(key).and_then(|items| items.as_array_mut()) {
+        let mut i = 0;
+        items.retain(|_| {
+            i += 1;
+            remap[i - 1] == i - 1
+        });
+    }
+    index
+}
+
+fn component_size(component_type: u64) -> usize {
+    match component_type {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        _ => 4,
+    }
+}
+
+fn component_count(accessor_type: &str) -> usize {
+    match accessor_type {
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" | "MAT2" => 4,
+        "MAT3" => 9,
+        "MAT4" => 16,
+        _ => 1,
+    }
+}
+
+/// The tightly packed bytes of an accessor, so the same data compares equal regardless of where
+/// in the buffers it's stored.
+fn accessor_bytes(gltf: &Value, buffers: &[Vec<u8>], accessor: u64) -> anyhow::Result<Vec<u8>> {
+    let accessor = &gltf["accessors"][accessor as usize];
+    let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+    let element_size = component_size(accessor["componentType"].as_u64().unwrap_or(0))
+        * component_count(accessor["type"].as_str().unwrap_or(""));
+    let Some(view) = accessor["bufferView"].as_u64() else {
+        // Without a buffer view the accessor is all zeros
+        return Ok(vec![0; count * element_size]);
+    };
+    if accessor.get("sparse").is_some() {
+        bail!("Sparse accessors aren't supported");
+    }
+    let view = &gltf["bufferViews"][view as usize];
+    let buffer = buffers
+        .get(view["buffer"].as_u64().unwrap_or(0) as usize)
+        .ok_or_else(|| anyhow!("Invalid buffer view"))?;
+    let start = view["byteOffset"].as_u64().unwrap_or(0) as usize
+        + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let stride = view["byteStride"]
+        .as_u64()
+        .map_or(element_size, |stride| stride as usize);
+    let mut bytes = Vec::with_capacity(count * element_size);
+    for i in 0..count {
+        let offset = start + i * stride;
+        bytes.extend_from_slice(
+            buffer
+                .get(offset..offset + element_size)
+                .ok_or_else(|| anyhow!("Accessor is out of bounds of its buffer"))?,
+        );
+    }
+    Ok(bytes)
+}
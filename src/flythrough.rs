@@ -0,0 +1,129 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use bevy_hidden_alley_scene::SCENE_SETTLE_FRAMES;
+
+use crate::{Args, BenchPositions, BenchmarkCamera, BenchmarkRunning};
+
+#[derive(Default)]
+pub struct Flythrough {
+    settled_frames: u32,
+    started: Option<Instant>,
+    last_frame: Option<Instant>,
+    /// Frame times in ms for each segment of the path
+    segments: Vec<Vec<f32>>,
+    done: bool,
+}
+
+/// The camera transform `distance` along the path through `positions`, moving at a constant
+/// speed and turning evenly along each leg.
+fn transform_along_path(positions: &[Transform], distance: f32) -> Transform {
+    let mut remaining = distance;
+    for leg in positions.windows(2) {
+        let length = leg[0].translation.distance(leg[1].translation);
+        if remaining <= length {
+            let t = if length > 0.0 {
+                remaining / length
+            } else {
+                1.0
+            };
+            return Transform {
+                translation: leg[0].translation.lerp(leg[1].translation, t),
+                rotation: leg[0].rotation.slerp(leg[1].rotation, t),
+                scale: leg[0].scale.lerp(leg[1].scale, t),
+            };
+        }
+        remaining -= length;
+    }
+    positions.last().copied().unwrap_or_default()
+}
+
+fn path_length(positions: &[Transform]) -> f32 {
+    positions
+        .windows(2)
+        .map(|leg| leg[0].translation.distance(leg[1].translation))
+        .sum()
+}
+
+/// With `--flythrough <seconds>`, moves the camera along the benchmark positions at a constant
+/// speed once the scene has loaded, then prints the frame times of each stretch of the path so
+/// the expensive parts of the scene stand out.
+pub fn run_flythrough(
+    mut state: Local<Flythrough>,
+    args: Res<Args>,
+    positions: Res<BenchPositions>,
+    mut running: ResMut<BenchmarkRunning>,
+    mut camera: Query<&mut Transform, With<BenchmarkCamera>>,
+) {
+    let Some(duration) = args.flythrough else {
+        return;
+    };
+    if state.done || positions.0.len() < 2 {
+        return;
+    }
+    let Some(started) = state.started else {
+        if running.0 {
+            return;
+        }
+        state.settled_frames += 1;
+        if state.settled_frames >= SCENE_SETTLE_FRAMES {
+            info!("Starting flythrough over {duration}s");
+            state.started = Some(Instant::now());
+            state.segments = vec![Vec::new(); args.flythrough_segments.max(1)];
+            running.0 = true;
+        }
+        return;
+    };
+    let Ok(mut transform) = camera.get_single_mut() else {
+        return;
+    };
+
+    let now = Instant::now();
+    let t = (now - started).as_secs_f32() / duration;
+    if let Some(last_frame) = state.last_frame {
+        // The frame that just ended rendered the camera where it was placed last frame
+        let previous_t = (last_frame - started).as_secs_f32() / duration;
+        let segment =
+            ((previous_t * state.segments.len() as f32) as usize).min(state.segments.len() - 1);
+        state.segments[segment].push((now - last_frame).as_secs_f32() * 1000.0);
+    }
+    state.last_frame = Some(now);
+
+    let length = path_length(&positions.0);
+    if t < 1.0 {
+        *transform = transform_along_path(&positions.0, t * length);
+        return;
+    }
+    print_flythrough_results(&state.segments, length, duration);
+    running.0 = false;
+    state.done = true;
+}
+
+fn print_flythrough_results(segments: &[Vec<f32>], length: f32, duration: f32) {
+    println!("Flythrough results:");
+    println!(
+        "{:>15} {:>15} {:>7} {:>10} {:>10}",
+        "distance", "time", "frames", "avg", "p95"
+    );
+    let count = segments.len() as f32;
+    for (i, frame_times) in segments.iter().enumerate() {
+        let (start, end) = (i as f32 / count, (i + 1) as f32 / count);
+        let mut sorted = frame_times.clone();
+        sorted.sort_by(f32::total_cmp);
+        let avg = sorted.iter().sum::<f32>() / sorted.len().max(1) as f32;
+        let p95 = sorted
+            .get(sorted.len() * 95 / 100)
+            .copied()
+            .unwrap_or_default();
+        println!(
+            "{:>6.1}-{:>6.1}m {:>6.2}-{:>6.2}s {:>7} {:>8.2}ms {:>8.2}ms",
+            start * length,
+            end * length,
+            start * duration,
+            end * duration,
+            sorted.len(),
+            avg,
+            p95
+        );
+    }
+}
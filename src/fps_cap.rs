@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::{Args, BenchmarkRunning};
+
+/// Frame rate Y caps to when `--fps-cap` isn't given.
+const DEFAULT_FPS_CAP: f32 = 60.0;
+
+/// Limits the frame rate by sleeping at the end of each frame, from `--fps-cap`.
+#[derive(Resource)]
+pub struct FpsCap {
+    pub fps: f32,
+    pub enabled: bool,
+}
+
+impl FpsCap {
+    pub fn from_args(args: &Args) -> Self {
+        FpsCap {
+            fps: args.fps_cap.unwrap_or(DEFAULT_FPS_CAP).max(1.0),
+            enabled: args.fps_cap.is_some(),
+        }
+    }
+}
+
+/// Press Y to turn the frame rate cap on or off.
+pub fn toggle_fps_cap(keys: Res<ButtonInput<KeyCode>>, mut cap: ResMut<FpsCap>) {
+    if !keys.just_pressed(KeyCode::KeyY) {
+        return;
+    }
+    cap.enabled = !cap.enabled;
+    if cap.enabled {
+        info!("FPS cap: {}", cap.fps);
+    } else {
+        info!("FPS cap: off");
+    }
+}
+
+/// Sleeps until a whole frame interval has passed since the previous frame ended. Skipped while
+/// `--bench-uncapped` runs the benchmark.
+pub fn limit_frame_rate(
+    cap: Res<FpsCap>,
+    args: Res<Args>,
+    running: Res<BenchmarkRunning>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    if let (Some(last_frame), true) = (*last_frame, cap.enabled) {
+        let interval = Duration::from_secs_f32(1.0 / cap.fps);
+        let elapsed = last_frame.elapsed();
+        if elapsed < interval && !(args.bench_uncapped && running.0) {
+            std::thread::sleep(interval - elapsed);
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+/// Iterates over all descendants of `entity`, depth first, not including `entity` itself.
+pub fn descendants<'a, 'w, 's, 'c>(
+    entity: Entity,
+    children_query: &'a Query<'w, 's, &'c Children>,
+) -> Descendants<'a, 'w, 's, 'c> {
+    let mut descendants = Descendants {
+        children_query,
+        stack: Vec::new(),
+    };
+    descendants.push_children(entity);
+    descendants
+}
+
+pub struct Descendants<'a, 'w, 's, 'c> {
+    children_query: &'a Query<'w, 's, &'c Children>,
+    stack: Vec<Entity>,
+}
+
+impl Descendants<'_, '_, '_, '_> {
+    fn push_children(&mut self, entity: Entity) {
+        if let Ok(children) = self.children_query.get(entity) {
+            // Reversed so children are visited in order
+            self.stack.extend(children.iter().rev());
+        }
+    }
+}
+
+impl Iterator for Descendants<'_, '_, '_, '_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let entity = self.stack.pop()?;
+        self.push_children(entity);
+        Some(entity)
+    }
+}
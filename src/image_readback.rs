@@ -0,0 +1,226 @@
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        graph::CameraDriverLabel,
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageDataLayout,
+            Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderContext, RenderDevice},
+        Extract, Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+
+/// Copies render target images back from the GPU so they can be saved or inspected on the CPU.
+/// Images need `TextureUsages::COPY_SRC` to be read back.
+pub struct ImageReadbackPlugin;
+impl Plugin for ImageReadbackPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = channel();
+        app.insert_resource(ImageReadback {
+            requested: Vec::new(),
+            completed: HashMap::new(),
+            receiver: Mutex::new(receiver),
+        })
+        .add_systems(First, receive_readbacks);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(RenderReadbacks {
+                pending: Vec::new(),
+                jobs: Vec::new(),
+                sender,
+            })
+            .add_systems(ExtractSchedule, extract_readback_requests)
+            .add_systems(
+                Render,
+                (
+                    prepare_readback_buffers.in_set(RenderSet::PrepareResources),
+                    map_readback_buffers.in_set(RenderSet::Cleanup),
+                ),
+            );
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(ImageReadbackLabel, ImageReadbackNode);
+        graph.add_node_edge(CameraDriverLabel, ImageReadbackLabel);
+    }
+}
+
+#[derive(Resource)]
+pub struct ImageReadback {
+    requested: Vec<AssetId<Image>>,
+    completed: HashMap<AssetId<Image>, Image>,
+    receiver: Mutex<Receiver<(AssetId<Image>, Image)>>,
+}
+
+impl ImageReadback {
+    /// Read back the image after the current frame is rendered.
+    pub fn request(&mut self, image: impl Into<AssetId<Image>>) {
+        self.requested.push(image.into());
+    }
+
+    /// Take the CPU copy of an image once its readback has completed.
+    pub fn take(&mut self, image: impl Into<AssetId<Image>>) -> Option<Image> {
+        self.completed.remove(&image.into())
+    }
+}
+
+fn receive_readbacks(mut readback: ResMut<ImageReadback>) {
+    // Requests were extracted at the end of the previous frame
+    readback.requested.clear();
+    let received = readback
+        .receiver
+        .lock()
+        .unwrap()
+        .try_iter()
+        .collect::<Vec<_>>();
+    readback.completed.extend(received);
+}
+
+/// Creates an image that can be used as a camera render target and read back with [`ImageReadback`].
+pub fn readback_render_target(width: u32, height: u32) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage |= TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
+#[derive(Resource)]
+struct RenderReadbacks {
+    pending: Vec<AssetId<Image>>,
+    jobs: Vec<ReadbackJob>,
+    sender: Sender<(AssetId<Image>, Image)>,
+}
+
+struct ReadbackJob {
+    image: AssetId<Image>,
+    buffer: Buffer,
+    size: Extent3d,
+    format: TextureFormat,
+    padded_row_bytes: u32,
+}
+
+fn extract_readback_requests(
+    mut readbacks: ResMut<RenderReadbacks>,
+    readback: Extract<Res<ImageReadback>>,
+) {
+    readbacks.pending.extend(readback.requested.iter().copied());
+}
+
+fn prepare_readback_buffers(
+    mut readbacks: ResMut<RenderReadbacks>,
+    render_device: Res<RenderDevice>,
+    gpu_images: Res<RenderAssets<Image>>,
+) {
+    let pending = std::mem::take(&mut readbacks.pending);
+    for image in pending {
+        let Some(gpu_image) = gpu_images.get(image) else {
+            warn!("Image {:?} is not on the GPU yet, skipping readback", image);
+            continue;
+        };
+        let size = gpu_image.texture.size();
+        let pixel_size = gpu_image.texture_format.block_copy_size(None).unwrap();
+        let row_bytes = size.width * pixel_size;
+        let padded_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes as usize) as u32;
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("image_readback_buffer"),
+            size: (padded_row_bytes * size.height) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        readbacks.jobs.push(ReadbackJob {
+            image,
+            buffer,
+            size,
+            format: gpu_image.texture_format,
+            padded_row_bytes,
+        });
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, RenderLabel)]
+struct ImageReadbackLabel;
+
+struct ImageReadbackNode;
+impl Node for ImageReadbackNode {
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let readbacks = world.resource::<RenderReadbacks>();
+        let gpu_images = world.resource::<RenderAssets<Image>>();
+        for job in &readbacks.jobs {
+            let Some(gpu_image) = gpu_images.get(job.image) else {
+                continue;
+            };
+            render_context.command_encoder().copy_texture_to_buffer(
+                gpu_image.texture.as_image_copy(),
+                ImageCopyBuffer {
+                    buffer: &job.buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(job.padded_row_bytes),
+                        rows_per_image: None,
+                    },
+                },
+                job.size,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Runs after the frame was submitted. Blocks until the copies are done, which is fine for
+/// the occasional screenshot but would not be for every frame.
+fn map_readback_buffers(mut readbacks: ResMut<RenderReadbacks>, render_device: Res<RenderDevice>) {
+    let jobs = std::mem::take(&mut readbacks.jobs);
+    for job in jobs {
+        let slice = job.buffer.slice(..);
+        let (tx, rx) = channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        render_device.poll(Maintain::Wait);
+        if let Ok(Err(e)) = rx.recv() {
+            warn!("Failed to read back image {:?}: {}", job.image, e);
+            continue;
+        }
+        let padded = slice.get_mapped_range();
+        let row_bytes = (job.size.width * job.format.block_copy_size(None).unwrap()) as usize;
+        let mut data = Vec::with_capacity(row_bytes * job.size.height as usize);
+        for row in padded.chunks(job.padded_row_bytes as usize) {
+            data.extend_from_slice(&row[..row_bytes]);
+        }
+        drop(padded);
+        job.buffer.unmap();
+
+        let image = Image::new(
+            job.size,
+            TextureDimension::D2,
+            data,
+            job.format,
+            RenderAssetUsages::default(),
+        );
+        let _ = readbacks.sender.send((job.image, image));
+    }
+}
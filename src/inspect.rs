@@ -0,0 +1,86 @@
+use bevy::{prelude::*, render::primitives::Aabb, window::PrimaryWindow};
+use bevy_hidden_alley_scene::WasInstanced;
+
+use crate::{raycast::raycast_aabbs, scene_stats::triangle_count, BenchmarkCamera};
+
+/// Left click a mesh to print its entity, mesh, material and triangle count and outline its
+/// bounding box. Clicking nothing clears the outline.
+#[allow(clippy::type_complexity)]
+pub fn inspect_mesh(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut inspected: Local<Option<Entity>>,
+    mut gizmos: Gizmos,
+    meshes: Res<Assets<Mesh>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<BenchmarkCamera>>,
+    entities: Query<(
+        Entity,
+        &Aabb,
+        &GlobalTransform,
+        &ViewVisibility,
+        &Handle<Mesh>,
+        Option<&Handle<StandardMaterial>>,
+        Option<&Name>,
+        Option<&WasInstanced<Mesh>>,
+        Option<&WasInstanced<StandardMaterial>>,
+    )>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        let cursor = windows.get_single().ok().and_then(|w| w.cursor_position());
+        let (Some(cursor), Ok((camera, camera_transform))) = (cursor, cameras.get_single()) else {
+            return;
+        };
+        let Some(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+            return;
+        };
+        let visible = entities
+            .iter()
+            .filter(|(_, _, _, visibility, ..)| visibility.get())
+            .map(|(entity, aabb, transform, ..)| (entity, aabb, transform));
+        *inspected = raycast_aabbs(ray, visible).map(|(entity, _)| entity);
+        if let Some((entity, _, _, _, mesh_h, mat_h, name, mesh_instanced, mat_instanced)) =
+            inspected.and_then(|entity| entities.get(entity).ok())
+        {
+            // Entities sharing a handle were either instanced or shared it in the glTF already
+            let mesh_users = entities
+                .iter()
+                .filter(|(_, _, _, _, m, ..)| *m == mesh_h)
+                .count();
+            let mat_users = entities
+                .iter()
+                .filter(|(_, _, _, _, _, m, ..)| m.is_some() && *m == mat_h)
+                .count();
+            println!(
+                "Inspected {:?}{}",
+                entity,
+                name.map(|name| format!(" ({})", name)).unwrap_or_default()
+            );
+            println!(
+                "  Mesh {:?}, {} triangles, shared by {} entities",
+                mesh_h.id(),
+                meshes.get(mesh_h).map(triangle_count).unwrap_or(0),
+                mesh_users
+            );
+            if let Some(instanced) = mesh_instanced {
+                println!("  Mesh instanced onto {:?}", instanced.canonical);
+            }
+            match mat_h {
+                Some(mat_h) => println!(
+                    "  Material {:?}, shared by {} entities",
+                    mat_h.id(),
+                    mat_users
+                ),
+                None => println!("  No StandardMaterial"),
+            }
+            if let Some(instanced) = mat_instanced {
+                println!("  Material instanced onto {:?}", instanced.canonical);
+            }
+        }
+    }
+
+    if let Some((_, aabb, transform, ..)) = inspected.and_then(|e| entities.get(e).ok()) {
+        let bounds = Transform::from_translation(aabb.center.into())
+            .with_scale(Vec3::from(aabb.half_extents) * 2.0);
+        gizmos.cuboid(transform.mul_transform(bounds), Color::YELLOW);
+    }
+}
@@ -0,0 +1,26 @@
+//! The reusable plugins behind the hidden alley scene. The scene itself lives in the binary.
+
+pub mod auto_instance;
+pub mod camera_controller;
+pub mod hierarchy;
+pub mod image_readback;
+pub mod mipmap_generator;
+pub mod proc_scene;
+pub mod schedule;
+pub mod stable_hash;
+
+pub use auto_instance::{
+    AutoInstanceMaterial, AutoInstanceMaterialPlugin, AutoInstanceMaterialRecursive,
+    AutoInstanceMesh, AutoInstanceMeshRecursive, AutoInstancePlugin, AutoInstanceSettings,
+    MaterialHash, MaterialInstanceKey, WasInstanced,
+};
+pub use camera_controller::{CameraController, CameraControllerPlugin};
+pub use image_readback::{ImageReadback, ImageReadbackPlugin};
+pub use mipmap_generator::{
+    generate_mipmaps, MipmapGeneratorPlugin, MipmapGeneratorSettings, MipmapReport,
+    NoMipmapGeneration,
+};
+pub use proc_scene::{
+    EmissiveSettings, GlassSettings, PostProcScene, ProcScenePlugin, ProcSceneSettings,
+};
+pub use schedule::{scene_ready, SceneSet, SCENE_SETTLE_FRAMES};
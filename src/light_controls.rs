@@ -0,0 +1,253 @@
+use bevy::{
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder, DirectionalLightShadowMap},
+    prelude::*,
+};
+
+use crate::{GrifLight, Sun};
+
+/// The `GrifLight` currently targeted by the runtime light controls.
+#[derive(Resource, Default)]
+pub struct SelectedLight(pub Option<Entity>);
+
+pub fn light_label(entity: Entity, name: Option<&Name>) -> String {
+    name.map(|name| name.to_string())
+        .unwrap_or_else(|| format!("{:?}", entity))
+}
+
+/// Press Tab to cycle the selected light through the `GrifLight` entities.
+pub fn select_light(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut selected: ResMut<SelectedLight>,
+    lights: Query<(Entity, Option<&Name>), With<GrifLight>>,
+) {
+    if !keys.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    let mut lights = lights.iter().collect::<Vec<_>>();
+    if lights.is_empty() {
+        return;
+    }
+    // Sort so the cycle order is stable between frames
+    lights.sort_by_key(|(entity, _)| *entity);
+    let next = selected
+        .0
+        .and_then(|current| lights.iter().position(|(entity, _)| *entity == current))
+        .map(|i| (i + 1) % lights.len())
+        .unwrap_or(0);
+    let (entity, name) = lights[next];
+    selected.0 = Some(entity);
+    info!("Selected light: {}", light_label(entity, name));
+}
+
+/// Press PageUp/PageDown to raise/lower the intensity of the selected light.
+#[allow(clippy::type_complexity)]
+pub fn adjust_light_intensity(
+    keys: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedLight>,
+    mut lights: Query<
+        (
+            Option<&Name>,
+            Option<&mut DirectionalLight>,
+            Option<&mut PointLight>,
+            Option<&mut SpotLight>,
+        ),
+        With<GrifLight>,
+    >,
+) {
+    let factor = if keys.just_pressed(KeyCode::PageUp) {
+        1.25
+    } else if keys.just_pressed(KeyCode::PageDown) {
+        1.0 / 1.25
+    } else {
+        return;
+    };
+    let Some(entity) = selected.0 else {
+        info!("No light selected, press Tab to select one");
+        return;
+    };
+    let Ok((name, directional, point, spot)) = lights.get_mut(entity) else {
+        return;
+    };
+    let intensity = if let Some(mut light) = directional {
+        light.illuminance *= factor;
+        light.illuminance
+    } else if let Some(mut light) = point {
+        light.intensity *= factor;
+        light.intensity
+    } else if let Some(mut light) = spot {
+        light.intensity *= factor;
+        light.intensity
+    } else {
+        return;
+    };
+    info!("{} intensity: {}", light_label(entity, name), intensity);
+}
+
+/// Press X to mute/unmute the selected light by toggling its visibility.
+pub fn toggle_selected_light(
+    keys: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedLight>,
+    mut lights: Query<(Entity, Option<&Name>, &mut Visibility), With<GrifLight>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+    let Some(entity) = selected.0 else {
+        info!("No light selected, press Tab to select one");
+        return;
+    };
+    if let Ok((_, _, mut visibility)) = lights.get_mut(entity) {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+    let muted = lights
+        .iter()
+        .filter(|(_, _, visibility)| **visibility == Visibility::Hidden)
+        .map(|(entity, name, _)| light_label(entity, name))
+        .collect::<Vec<_>>();
+    if muted.is_empty() {
+        info!("Muted lights: none");
+    } else {
+        info!("Muted lights: {}", muted.join(", "));
+    }
+}
+
+/// Below this the ambient light snaps to off when lowered, and turns on at this when raised.
+const MIN_AMBIENT_BRIGHTNESS: f32 = 20.0;
+
+/// Press Home/End to raise/lower the ambient light, for looking into dark corners.
+pub fn adjust_ambient_light(keys: Res<ButtonInput<KeyCode>>, mut ambient: ResMut<AmbientLight>) {
+    if keys.just_pressed(KeyCode::Home) {
+        ambient.brightness = (ambient.brightness * 1.5).max(MIN_AMBIENT_BRIGHTNESS);
+    } else if keys.just_pressed(KeyCode::End) {
+        ambient.brightness /= 1.5;
+        if ambient.brightness < MIN_AMBIENT_BRIGHTNESS {
+            ambient.brightness = 0.0;
+        }
+    } else {
+        return;
+    }
+    info!("Ambient light brightness: {}", ambient.brightness);
+}
+
+/// Color temperature of the sun in kelvin, converted to its color with [`color_temperature`].
+#[derive(Resource)]
+pub struct SunTemperature(pub f32);
+
+/// The sun color from `color_temperature` is scaled by this to keep its brightness from before
+/// it was set by temperature.
+const SUN_COLOR_SCALE: f32 = 0.95;
+const SUN_TEMPERATURE_STEP: f32 = 250.0;
+
+/// Approximate color of a black body at `kelvin` (1000K-40000K), using Tanner Helland's fit.
+pub fn color_temperature(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let (r, g, b) = if t <= 66.0 {
+        let b = if t <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * (t - 10.0).ln() - 305.044_8
+        };
+        (255.0, 99.470_8 * t.ln() - 161.119_57, b)
+    } else {
+        (
+            329.698_73 * (t - 60.0).powf(-0.133_204_76),
+            288.122_17 * (t - 60.0).powf(-0.075_514_85),
+            255.0,
+        )
+    };
+    Color::rgb(
+        (r / 255.0).clamp(0.0, 1.0),
+        (g / 255.0).clamp(0.0, 1.0),
+        (b / 255.0).clamp(0.0, 1.0),
+    )
+}
+
+pub fn sun_color(kelvin: f32) -> Color {
+    color_temperature(kelvin).as_rgba_linear() * SUN_COLOR_SCALE
+}
+
+/// Press J/K to make the sun warmer/cooler.
+pub fn adjust_sun_temperature(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut temperature: ResMut<SunTemperature>,
+    mut lights: Query<&mut DirectionalLight, With<Sun>>,
+) {
+    let step = if keys.just_pressed(KeyCode::KeyK) {
+        SUN_TEMPERATURE_STEP
+    } else if keys.just_pressed(KeyCode::KeyJ) {
+        -SUN_TEMPERATURE_STEP
+    } else {
+        return;
+    };
+    temperature.0 = (temperature.0 + step).clamp(1000.0, 12000.0);
+    for mut light in &mut lights {
+        light.color = sun_color(temperature.0);
+    }
+    info!("Sun temperature: {}K", temperature.0);
+}
+
+const SHADOW_MAP_SIZES: [usize; 3] = [1024, 2048, 4096];
+
+/// Press F5 to cycle the directional light shadow map resolution.
+pub fn cycle_shadow_map_size(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+) {
+    if !keys.just_pressed(KeyCode::F5) {
+        return;
+    }
+    shadow_map.size = SHADOW_MAP_SIZES
+        .iter()
+        .find(|size| **size > shadow_map.size)
+        .copied()
+        .unwrap_or(SHADOW_MAP_SIZES[0]);
+    info!("Shadow map size: {}", shadow_map.size);
+}
+
+/// Range and step factor for adjusting the sun's cascade `maximum_distance` with ,/.
+const MIN_CASCADE_DISTANCE: f32 = 10.0;
+const MAX_CASCADE_DISTANCE: f32 = 400.0;
+const CASCADE_DISTANCE_STEP: f32 = 1.25;
+
+/// Press ,/. to shorten/lengthen the distance the sun's shadow cascades cover. Combine with
+/// the `--debug-cascades` tint, toggled with F2, to see where each cascade ends.
+pub fn adjust_cascade_distance(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut lights: Query<&mut CascadeShadowConfig, With<Sun>>,
+) {
+    let factor = if keys.just_pressed(KeyCode::Period) {
+        CASCADE_DISTANCE_STEP
+    } else if keys.just_pressed(KeyCode::Comma) {
+        1.0 / CASCADE_DISTANCE_STEP
+    } else {
+        return;
+    };
+    for mut cascades in &mut lights {
+        let maximum_distance = (cascades.bounds.last().copied().unwrap_or(40.0) * factor)
+            .clamp(MIN_CASCADE_DISTANCE, MAX_CASCADE_DISTANCE);
+        *cascades = CascadeShadowConfigBuilder {
+            num_cascades: cascades.bounds.len().max(1),
+            maximum_distance,
+            ..default()
+        }
+        .into();
+        info!("Sun cascade maximum distance: {maximum_distance:.1}");
+    }
+}
+
+/// Press H to toggle the sun's shadows, the most expensive part of the scene.
+pub fn toggle_sun_shadows(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut lights: Query<&mut DirectionalLight, With<Sun>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyH) {
+        return;
+    }
+    for mut light in &mut lights {
+        light.shadows_enabled = !light.shadows_enabled;
+        info!("Sun shadows: {}", light.shadows_enabled);
+    }
+}
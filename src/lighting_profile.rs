@@ -0,0 +1,198 @@
+use std::{fs, path::Path};
+
+use bevy::{asset::ron, prelude::*};
+use serde::Deserialize;
+
+use crate::{light_controls::sun_color, Args, SkyColor, Sun};
+
+/// Euler XYZ rotation of the sun in `setup`.
+pub const SUN_ROTATION: (f32, f32, f32) = (-1.8327503, -0.41924718, 0.0);
+pub const SUN_ILLUMINANCE: f32 = 3000000.0 * 0.2;
+const HOURS_PER_DAY: f32 = 24.0;
+const TIME_OF_DAY_STEP: f32 = 0.5;
+
+/// The lighting at one time of day.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct LightingKeyframe {
+    /// Hours, 0-24
+    pub time: f32,
+    /// Euler XYZ rotation in radians
+    pub sun_rotation: (f32, f32, f32),
+    pub sun_color: Color,
+    pub sun_illuminance: f32,
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    /// Clear and fog color, already brightened for the HDR camera
+    pub sky_color: Color,
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let a = Vec4::from(a.as_linear_rgba_f32());
+    let b = Vec4::from(b.as_linear_rgba_f32());
+    let [r, g, b, alpha] = a.lerp(b, t).to_array();
+    Color::rgba_linear(r, g, b, alpha)
+}
+
+impl LightingKeyframe {
+    fn lerp(&self, other: &LightingKeyframe, t: f32) -> LightingKeyframe {
+        let rotation = |(x, y, z)| Quat::from_euler(EulerRot::XYZ, x, y, z);
+        let (x, y, z) = rotation(self.sun_rotation)
+            .slerp(rotation(other.sun_rotation), t)
+            .to_euler(EulerRot::XYZ);
+        LightingKeyframe {
+            time: self.time + (other.time - self.time).rem_euclid(HOURS_PER_DAY) * t,
+            sun_rotation: (x, y, z),
+            sun_color: lerp_color(self.sun_color, other.sun_color, t),
+            sun_illuminance: lerp(self.sun_illuminance, other.sun_illuminance, t),
+            ambient_color: lerp_color(self.ambient_color, other.ambient_color, t),
+            ambient_brightness: lerp(self.ambient_brightness, other.ambient_brightness, t),
+            sky_color: lerp_color(self.sky_color, other.sky_color, t),
+        }
+    }
+}
+
+/// Keyframes of the sun, ambient light and sky over a day, loaded from a `.ron` file with
+/// `--lighting-profile`. Without one, the lighting from the command line is the only keyframe.
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct LightingProfile {
+    pub keyframes: Vec<LightingKeyframe>,
+}
+
+impl LightingProfile {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut profile: LightingProfile = ron::from_str(&fs::read_to_string(path)?)?;
+        if profile.keyframes.is_empty() {
+            anyhow::bail!("The lighting profile has no keyframes");
+        }
+        profile.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(profile)
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        LightingProfile {
+            keyframes: vec![LightingKeyframe {
+                time: 12.0,
+                sun_rotation: SUN_ROTATION,
+                sun_color: sun_color(args.sun_temp),
+                sun_illuminance: SUN_ILLUMINANCE,
+                ambient_color: args.ambient_color.unwrap_or(Color::WHITE),
+                ambient_brightness: args.ambient_brightness,
+                sky_color: args.sky_color(),
+            }],
+        }
+    }
+
+    /// The lighting at `time` hours, interpolated between the keyframes around it and wrapping
+    /// around midnight.
+    pub fn sample(&self, time: f32) -> LightingKeyframe {
+        let time = time.rem_euclid(HOURS_PER_DAY);
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(0);
+        let previous = &self.keyframes[(next + self.keyframes.len() - 1) % self.keyframes.len()];
+        let next = &self.keyframes[next];
+        let span = (next.time - previous.time).rem_euclid(HOURS_PER_DAY);
+        if span == 0.0 {
+            return previous.clone();
+        }
+        previous.lerp(
+            next,
+            (time - previous.time).rem_euclid(HOURS_PER_DAY) / span,
+        )
+    }
+}
+
+/// Hours into the day the lighting profile is sampled at.
+#[derive(Resource)]
+pub struct TimeOfDay(pub f32);
+
+/// Press T to move the time of day forward, Shift+T to move it back.
+pub fn adjust_time_of_day(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<TimeOfDay>) {
+    if !keys.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+    let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        -TIME_OF_DAY_STEP
+    } else {
+        TIME_OF_DAY_STEP
+    };
+    time.0 = (time.0 + step).rem_euclid(HOURS_PER_DAY);
+    info!("Time of day: {:.1}h", time.0);
+}
+
+/// Applies the lighting profile at the current time of day to the sun, ambient light and sky
+/// whenever either changes, so other lighting tweaks stay until the time changes.
+pub fn apply_lighting_profile(
+    profile: Res<LightingProfile>,
+    time: Res<TimeOfDay>,
+    mut sun: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sky_color: ResMut<SkyColor>,
+) {
+    if !profile.is_changed() && !time.is_changed() {
+        return;
+    }
+    let lighting = profile.sample(time.0);
+    let (x, y, z) = lighting.sun_rotation;
+    for (mut transform, mut light) in &mut sun {
+        transform.rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+        light.color = lighting.sun_color;
+        light.illuminance = lighting.sun_illuminance;
+    }
+    ambient.color = lighting.ambient_color;
+    ambient.brightness = lighting.ambient_brightness;
+    sky_color.0 = lighting.sky_color;
+}
+
+#[cfg(test)]
+mod tests {
+    use argh::FromArgs;
+
+    use super::*;
+
+    #[test]
+    fn default_lighting_profile_matches_setup() {
+        let args = Args::from_args(&["bevy_hidden_alley_scene"], &[]).unwrap();
+        let shipped = LightingProfile::load("assets/lighting_profiles/default.ron").unwrap();
+        let setup = LightingProfile::from_args(&args);
+        assert_eq!(shipped.keyframes.len(), 1);
+        let (shipped, setup) = (&shipped.keyframes[0], &setup.keyframes[0]);
+        assert_eq!(shipped.sun_rotation, setup.sun_rotation);
+        assert_eq!(shipped.sun_illuminance, setup.sun_illuminance);
+        assert_eq!(shipped.ambient_brightness, setup.ambient_brightness);
+        for (a, b) in [
+            (shipped.sun_color, setup.sun_color),
+            (shipped.ambient_color, setup.ambient_color),
+            (shipped.sky_color, setup.sky_color),
+        ] {
+            let (a, b) = (a.as_linear_rgba_f32(), b.as_linear_rgba_f32());
+            assert!(
+                Vec4::from(a).abs_diff_eq(Vec4::from(b), 1e-3),
+                "{a:?} != {b:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn lighting_profile_wraps_around_midnight() {
+        let args = Args::from_args(&["bevy_hidden_alley_scene"], &[]).unwrap();
+        let keyframe = |time, sun_illuminance| LightingKeyframe {
+            time,
+            sun_illuminance,
+            ..LightingProfile::from_args(&args).keyframes[0].clone()
+        };
+        let profile = LightingProfile {
+            keyframes: vec![keyframe(6.0, 100.0), keyframe(18.0, 300.0)],
+        };
+        assert_eq!(profile.sample(12.0).sun_illuminance, 200.0);
+        assert_eq!(profile.sample(0.0).sun_illuminance, 200.0);
+        assert_eq!(profile.sample(21.0).sun_illuminance, 250.0);
+        assert_eq!(profile.sample(30.0).sun_illuminance, 100.0);
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_hidden_alley_scene::mipmap_generator::MipmapTasks;
+
+use bevy_hidden_alley_scene::PostProcScene;
+
+const LOADING_SCREEN_COLOR: Color = Color::rgb(0.05, 0.05, 0.06);
+const LOADING_SCREEN_FADE_SECONDS: f32 = 0.5;
+
+#[derive(Component)]
+pub struct LoadingScreen;
+
+#[derive(Component)]
+pub struct LoadingText;
+
+/// Skipped without a window, like with `--verify-against`, since UI without a camera on a
+/// window panics in bevy's layout.
+pub fn spawn_loading_screen(mut commands: Commands, windows: Query<(), With<PrimaryWindow>>) {
+    if windows.is_empty() {
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: LOADING_SCREEN_COLOR.into(),
+                z_index: ZIndex::Global(i32::MAX),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Loading scene",
+                    TextStyle {
+                        font_size: 32.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                LoadingText,
+            ));
+        });
+}
+
+/// Covers the window until the scene has spawned and its mipmaps are generated, then fades out
+/// and despawns. Piped from [`scene_ready`](bevy_hidden_alley_scene::scene_ready).
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn update_loading_screen(
+    In(ready): In<bool>,
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fade_started: Local<Option<f32>>,
+    mip_tasks: Option<Res<MipmapTasks<StandardMaterial>>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    mut screen: Query<(Entity, &mut BackgroundColor), With<LoadingScreen>>,
+    mut text: Query<&mut Text, With<LoadingText>>,
+) {
+    let Ok((entity, mut background)) = screen.get_single_mut() else {
+        return;
+    };
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+
+    let Some(started) = *fade_started else {
+        let pending_mips = mip_tasks.as_ref().map_or(0, |tasks| tasks.len());
+        text.sections[0].value = if !loading_scenes.is_empty() {
+            "Loading scene".to_string()
+        } else if pending_mips > 0 {
+            format!("Generating mipmaps ({pending_mips} remaining)")
+        } else {
+            "Loading textures".to_string()
+        };
+        if ready {
+            *fade_started = Some(time.elapsed_seconds());
+        }
+        return;
+    };
+
+    let alpha = 1.0 - (time.elapsed_seconds() - started) / LOADING_SCREEN_FADE_SECONDS;
+    if alpha <= 0.0 {
+        commands.entity(entity).despawn_recursive();
+        return;
+    }
+    background.0.set_a(alpha);
+    text.sections[0].style.color.set_a(alpha);
+}
@@ -1,49 +1,220 @@
-use std::{f32::consts::PI, time::Instant};
-
-mod camera_controller;
-mod mipmap_generator;
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
 
 use argh::FromArgs;
-use auto_instance::{AutoInstanceMaterialPlugin, AutoInstancePlugin};
 use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
     core_pipeline::{
         bloom::BloomSettings,
         experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasPlugin},
+        prepass::{DeferredPrepass, DepthPrepass},
     },
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    ecs::query::QuerySingleError,
     input::mouse::MouseMotion,
+    log::LogPlugin,
     math::vec3,
     pbr::{
-        CascadeShadowConfigBuilder, ScreenSpaceAmbientOcclusionBundle, TransmittedShadowReceiver,
+        CascadeShadowConfigBuilder, DefaultOpaqueRendererMethod, DirectionalLightShadowMap,
+        ScreenSpaceAmbientOcclusionBundle,
     },
     prelude::*,
     render::view::{ColorGrading, NoFrustumCulling},
-    window::{PresentMode, WindowResolution},
-    winit::{UpdateMode, WinitSettings},
+    time::TimeUpdateStrategy,
+    window::{ExitCondition, PresentMode, PrimaryWindow, WindowResolution},
+    winit::{UpdateMode, WinitPlugin, WinitSettings},
+};
+use bevy_hidden_alley_scene::{
+    mipmap_generator::MipmapTasks,
+    proc_scene::{EmissiveSettings, GlassSettings, ProcScenePlugin, ProcSceneSettings},
+    scene_ready, AutoInstanceMaterialPlugin, AutoInstanceMaterialRecursive,
+    AutoInstanceMeshRecursive, AutoInstancePlugin, AutoInstanceSettings, CameraController,
+    CameraControllerPlugin, ImageReadbackPlugin, MipmapGeneratorPlugin, MipmapGeneratorSettings,
+    PostProcScene, SceneSet,
 };
-use camera_controller::CameraControllerPlugin;
-use mipmap_generator::{generate_mipmaps, MipmapGeneratorPlugin, MipmapGeneratorSettings};
 
 use crate::{
-    auto_instance::{AutoInstanceMaterialRecursive, AutoInstanceMeshRecursive},
-    camera_controller::CameraController,
-    convert::{change_gltf_to_use_ktx2, convert_images_to_ktx2},
+    bench_budget::run_bench_budget,
+    benchmark_results::{BenchmarkResults, BENCHMARK_RESULTS_PATH},
+    convert::{change_gltf_to_use_ktx2, convert_images_to_ktx2, gltf_paths, ConvertFormat},
+    debug_views::{
+        cycle_instancing_filter, draw_bench_path, draw_grid, draw_instancing_debug_view,
+        set_time_scale, toggle_ao_debug_view, toggle_cascade_debug_view, toggle_grid, AoDebugView,
+        CascadeDebugMaterial, GridGizmo,
+    },
+    environment::{
+        adjust_environment_map_intensity, apply_environment_maps, blend_environment_maps,
+        check_environment_maps, check_ktx2_support, EnvironmentMaps,
+    },
+    export::export_optimized,
+    flythrough::run_flythrough,
+    fps_cap::{limit_frame_rate, toggle_fps_cap, FpsCap},
+    inspect::inspect_mesh,
+    light_controls::{
+        adjust_ambient_light, adjust_cascade_distance, adjust_light_intensity,
+        adjust_sun_temperature, cycle_shadow_map_size, light_label, select_light, sun_color,
+        toggle_selected_light, toggle_sun_shadows, SelectedLight, SunTemperature,
+    },
+    lighting_profile::{
+        adjust_time_of_day, apply_lighting_profile, LightingProfile, TimeOfDay, SUN_ILLUMINANCE,
+        SUN_ROTATION,
+    },
+    loading_screen::{spawn_loading_screen, update_loading_screen},
+    memory::{print_memory_summary, PeakMemory},
+    node_filter::filter_scene_nodes,
+    overlay::{spawn_overlay, update_overlay},
+    photo_mode::{settled_screenshot, supersampled_screenshot, toggle_photo_mode, PhotoMode},
+    projection::{adjust_fov, toggle_orthographic, MAX_FOV_DEGREES, MIN_FOV_DEGREES},
+    quality_presets::{cycle_msaa, switch_quality_preset},
+    render_settings::print_render_settings,
+    reset::{capture_initial_state, reset_tweaks},
+    scene_stats::{dump_hierarchy, list_cameras_and_lights, log_scene_stats},
+    spike_log::log_frame_spikes,
+    split_compare::{
+        bench_split_compare, parse_split_compare, setup_split_compare, update_split_compare,
+        SplitCompare, SplitCompareBench,
+    },
+    sweep::{parse_sweep, run_sweep, Sweep},
+    validate::run_validation,
+    verify::{setup_verify_target, verify_against_references},
 };
 
-mod auto_instance;
+mod bench_budget;
+mod benchmark_results;
 mod convert;
+mod debug_views;
+#[cfg(feature = "bevy_main")]
+mod depth_of_field;
+mod environment;
+mod export;
+mod flythrough;
+mod fps_cap;
+mod inspect;
+mod light_controls;
+mod lighting_profile;
+mod loading_screen;
+mod memory;
+mod node_filter;
+mod overlay;
+mod photo_mode;
+mod projection;
+mod quality_presets;
+mod raycast;
+mod render_settings;
+mod reset;
+mod scene_stats;
+mod spike_log;
+mod split_compare;
+mod sweep;
+mod validate;
+mod verify;
 
 #[derive(FromArgs, Resource, Clone)]
 /// Config
 pub struct Args {
-    /// convert gltf to use ktx
+    /// convert the --scene gltf to use ktx
     #[argh(switch)]
     convert: bool,
 
+    /// another gltf file, or a folder of them, to convert along with --scene. Relative to the
+    /// assets folder, can be repeated
+    #[argh(option)]
+    convert_gltf: Vec<String>,
+
+    /// ktx2 format for --convert, encoded with kram: bc7, astc or etc2
+    #[argh(option, default = "ConvertFormat::Bc7")]
+    convert_format: ConvertFormat,
+
+    /// don't generate mipmaps, for ktx2 textures that already have them. This also leaves out
+    /// the anisotropic filtering set up with the mipmaps
+    #[argh(switch)]
+    no_mipmaps: bool,
+
+    /// write a copy of the --scene gltf with duplicate meshes and materials merged to this
+    /// path, relative to the assets folder and in the same folder as the scene, then exit
+    #[argh(option)]
+    export_optimized: Option<String>,
+
+    /// load the scene without a window, run proc_scene and instancing, then report any meshes,
+    /// materials or textures that failed to load and exit, with an error if any did
+    #[argh(switch)]
+    validate: bool,
+
     /// enable auto instancing for meshes/materials
     #[argh(switch)]
     instance: bool,
 
+    /// render opaque materials with the deferred renderer instead of forward
+    #[argh(switch)]
+    deferred: bool,
+
+    /// render each benchmark position offscreen and compare it to position_<n>.png in this
+    /// directory, exiting with an error if any differs
+    #[argh(option)]
+    verify_against: Option<String>,
+
+    /// maximum mean per channel error (0-1) allowed by --verify-against
+    #[argh(option, default = "0.01")]
+    verify_tolerance: f32,
+
+    /// with --verify-against, save each capture as the new reference instead of comparing
+    #[argh(switch)]
+    update_references: bool,
+
+    /// enable depth of field, adjust with the arrow keys and F for auto-focus. Requires the bevy_main feature
+    #[argh(switch)]
+    dof: bool,
+
+    /// draw the benchmark camera positions and the path between them
+    #[argh(switch)]
+    debug_bench_path: bool,
+
+    /// draw a grid on the XZ plane through the origin, toggle with F6
+    #[argh(switch)]
+    grid: bool,
+
+    /// distance between the --grid lines
+    #[argh(option, default = "1.0")]
+    grid_spacing: f32,
+
+    /// distance from the origin the --grid reaches along X and Z
+    #[argh(option, default = "50.0")]
+    grid_extent: f32,
+
+    /// outline instanced and unique meshes, toggle with F3, and cycle between showing only
+    /// instanced or only unique meshes with F4. Requires --instance
+    #[argh(switch)]
+    debug_instancing: bool,
+
+    /// with --instance, report how many draws are merged into instanced draws
+    #[argh(switch)]
+    instance_report: bool,
+
+    /// with --instance, warn about meshes that weren't instanced but whose vertices are on
+    /// average within this distance of another mesh
+    #[argh(option)]
+    instance_warn_distance: Option<f32>,
+
+    /// with --instance, also instance meshes that are uniformly scaled copies of another mesh
+    #[argh(switch)]
+    instance_detect_scale: bool,
+
+    /// with --instance, only merge meshes between entities that also share a material, so each
+    /// group is a set of identical draws
+    #[argh(switch)]
+    instance_by_material: bool,
+
+    /// only log this crate's warnings and errors, hiding status messages. Reports like the
+    /// benchmark results are still printed
+    #[argh(switch)]
+    quiet: bool,
+
+    /// also log this crate's debug messages
+    #[argh(switch)]
+    verbose: bool,
+
     /// disable bloom, AO, AA, shadows
     #[argh(switch)]
     minimal: bool,
@@ -51,6 +222,308 @@ pub struct Args {
     /// whether to disable frustum culling.
     #[argh(switch)]
     no_frustum_culling: bool,
+
+    /// glTF scene to load, relative to the assets folder. Both .gltf and .glb are supported
+    #[argh(
+        option,
+        default = "String::from(\"hidden_alley/ph_hidden_alley_bevy_bake.gltf\")"
+    )]
+    scene: String,
+
+    /// diffuse environment map path, relative to the assets folder
+    #[argh(
+        option,
+        default = "String::from(\"environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2\")"
+    )]
+    env_diffuse: String,
+
+    /// specular environment map path, relative to the assets folder
+    #[argh(
+        option,
+        default = "String::from(\"environment_maps/pisa_specular_rgb9e5_zstd.ktx2\")"
+    )]
+    env_specular: String,
+
+    /// environment map intensity
+    #[argh(option, default = "1000.0")]
+    env_intensity: f32,
+
+    /// second diffuse environment map to blend to with ; and '
+    #[argh(option)]
+    env_diffuse_b: Option<String>,
+
+    /// second specular environment map to blend to with ; and '
+    #[argh(option)]
+    env_specular_b: Option<String>,
+
+    /// second environment map intensity, defaults to --env-intensity
+    #[argh(option)]
+    env_intensity_b: Option<f32>,
+
+    /// ambient light brightness, the scene is lit only by the environment map by default
+    #[argh(option, default = "0.0")]
+    ambient_brightness: f32,
+
+    /// ambient light color as hex, defaults to white
+    #[argh(option, from_str_fn(parse_hex_color))]
+    ambient_color: Option<Color>,
+
+    /// vertical camera field of view in degrees, between 20 and 120. Adjust with =/- or by
+    /// scrolling while holding Alt
+    #[argh(option, default = "60.0")]
+    fov: f32,
+
+    /// ron file with keyframes of the sun, ambient light and sky over a day, see
+    /// assets/lighting_profiles/default.ron. Move through the day with T and Shift+T
+    #[argh(option)]
+    lighting_profile: Option<String>,
+
+    /// hour of the day to start --lighting-profile at, defaults to its first keyframe
+    #[argh(option)]
+    time_of_day: Option<f32>,
+
+    /// sun color temperature in kelvin, adjust with J/K. The default matches the original warm sun
+    #[argh(option, default = "4750.0")]
+    sun_temp: f32,
+
+    /// degrees the sun or selected directional light turns per pixel of mouse movement while
+    /// dragging it with L
+    #[argh(option, default = "2.0")]
+    sun_drag_sensitivity: f32,
+
+    /// fraction of the way the sun moves toward the dragged rotation each frame, lower is
+    /// smoother
+    #[argh(option, default = "0.2")]
+    sun_drag_smoothing: f32,
+
+    /// add unshadowed fill and rim directional lights to the sun. Select one with Tab to drag it
+    /// with L or change its intensity
+    #[argh(switch)]
+    three_point: bool,
+
+    /// directional light shadow map resolution, cycle between sizes with F5. Defaults to bevy's
+    #[argh(option)]
+    shadow_map_size: Option<usize>,
+
+    /// tint the scene by shadow cascade, toggle with F2
+    #[argh(switch)]
+    debug_cascades: bool,
+
+    /// sky/fog color as hex, e.g. #e6e6ff. Brightened by 3x for the HDR camera
+    #[argh(option, from_str_fn(parse_hex_color))]
+    sky_color: Option<Color>,
+
+    /// resolution multiplier for supersampled screenshots taken with F12
+    #[argh(option, default = "2")]
+    screenshot_scale: u32,
+
+    /// frames the camera has to be still before F7 saves a screenshot, so TAA has converged
+    #[argh(option, default = "32")]
+    taa_settle_frames: u32,
+
+    /// seconds to animate between the camera positions on 1/2/3, hold Shift to jump instantly
+    #[argh(option, default = "0.0")]
+    camera_transition: f32,
+
+    /// number of times to repeat the benchmark when started, 0 loops until B is pressed again
+    #[argh(option, default = "1")]
+    bench_loop: u32,
+
+    /// seconds to measure each benchmark camera position for
+    #[argh(option, default = "2.0")]
+    bench_seconds: f32,
+
+    /// frames to render at each benchmark camera position before measuring, to leave out
+    /// pipeline compilation
+    #[argh(option, default = "10")]
+    bench_warmup: u32,
+
+    /// frames at the end of each benchmark camera position to leave out of the results
+    #[argh(option, default = "2")]
+    bench_cooldown: u32,
+
+    /// run the benchmark once per value of a setting and compare them, e.g.
+    /// shadow-distance:10,20,40,80. Supports shadow-distance, cascades and msaa
+    #[argh(option, from_str_fn(parse_sweep))]
+    sweep: Option<Sweep>,
+
+    /// benchmark_results.json from an earlier run to compare the benchmark against
+    #[argh(option)]
+    bench_baseline: Option<String>,
+
+    /// percentage a benchmark step may be slower than --bench-baseline before it's a regression
+    #[argh(option, default = "5.0")]
+    bench_tolerance: f32,
+
+    /// start the benchmark once the scene has loaded and exit when it finishes, with an error if
+    /// the frame time is over this budget. For CI
+    #[argh(option)]
+    bench_budget_ms: Option<f32>,
+
+    /// check the 99th percentile frame time against --bench-budget-ms instead of the average
+    #[argh(switch)]
+    bench_budget_p99: bool,
+
+    /// once the scene has loaded, fly the camera along the benchmark positions over this many
+    /// seconds and print the frame times for each stretch of the path
+    #[argh(option)]
+    flythrough: Option<f32>,
+
+    /// number of equal stretches --flythrough splits the path into
+    #[argh(option, default = "10")]
+    flythrough_segments: usize,
+
+    /// render the left and right half of the window with two camera configs to compare them,
+    /// e.g. full:no-ssao. Only per camera post processing can be compared: full, minimal,
+    /// no-bloom, no-fog, no-taa and no-ssao. Press F9 to benchmark each config fullscreen
+    #[argh(option, from_str_fn(parse_split_compare))]
+    split_compare: Option<SplitCompare>,
+
+    /// show the frame rate and the camera's speed, mode and mouse look state on screen
+    #[argh(switch)]
+    overlay: bool,
+
+    /// log frames that take much longer than the running average, with the camera transform
+    #[argh(switch)]
+    log_spikes: bool,
+
+    /// with --log-spikes, how many times the running average frame time counts as a spike
+    #[argh(option, default = "2.0")]
+    spike_threshold: f32,
+
+    /// limit the frame rate to this many frames per second, toggle with Y. Uncapped by default
+    #[argh(option)]
+    fps_cap: Option<f32>,
+
+    /// run the benchmark without vsync and with a fixed timestep, restoring the settings after
+    #[argh(switch)]
+    bench_uncapped: bool,
+
+    /// exit after this many seconds, with an error if the scene didn't load. For smoke tests
+    #[argh(option)]
+    run_seconds: Option<f32>,
+
+    /// print the entity tree of the scene once it has loaded, with names, meshes, materials and
+    /// alpha modes
+    #[argh(switch)]
+    dump_hierarchy: bool,
+
+    /// print every camera with its transform and projection once the scene has loaded
+    #[argh(switch)]
+    list_cameras: bool,
+
+    /// print every light with its type, color, intensity and shadows once the scene has loaded
+    #[argh(switch)]
+    list_lights: bool,
+
+    /// give alpha blended materials specular transmission, for glass
+    #[argh(switch)]
+    glass: bool,
+
+    /// scale the emissive of materials whose glTF material or entity name matches this
+    /// pattern, `*` matches anything, e.g. *_emissive
+    #[argh(option)]
+    emissive_pattern: Option<String>,
+
+    /// scale the emissive of every material whose emissive isn't black
+    #[argh(switch)]
+    emissive_non_black: bool,
+
+    /// emissive multiplier for --emissive-pattern and --emissive-non-black
+    #[argh(option, default = "4.0")]
+    emissive_multiplier: f32,
+
+    /// name of a mesh entity whose masked material should stay single-sided, can be repeated
+    #[argh(option)]
+    single_sided: Vec<String>,
+
+    /// only keep the scene nodes whose name matches this pattern, with their children, `*`
+    /// matches anything. For quickly testing part of the scene
+    #[argh(option)]
+    only_node: Option<String>,
+
+    /// number of copies of the scene to spawn side by side, to stress test instancing
+    #[argh(option, default = "1")]
+    duplicate_scene: u32,
+}
+
+impl Args {
+    /// The clear color and fog color need to match for the linear fog to blend into the sky
+    fn sky_color(&self) -> Color {
+        let color = self.sky_color.unwrap_or(Color::rgb(0.9, 0.9, 1.0));
+        Color::rgba(
+            color.r() * SKY_BRIGHTNESS,
+            color.g() * SKY_BRIGHTNESS,
+            color.b() * SKY_BRIGHTNESS,
+            color.a(),
+        )
+    }
+
+    /// `LogPlugin` filter with this crate's level set by --quiet or --verbose.
+    fn log_plugin(&self) -> LogPlugin {
+        let mut plugin = LogPlugin::default();
+        let level = match (self.quiet, self.verbose) {
+            (true, _) => "warn",
+            (false, true) => "debug",
+            (false, false) => return plugin,
+        };
+        plugin.filter = format!("{},{}={level}", plugin.filter, env!("CARGO_CRATE_NAME"));
+        plugin
+    }
+
+    fn proc_scene_settings(&self) -> ProcSceneSettings {
+        ProcSceneSettings {
+            single_sided: self.single_sided.iter().cloned().collect(),
+            glass: self.glass.then(GlassSettings::default),
+            emissive: (self.emissive_pattern.is_some() || self.emissive_non_black).then(|| {
+                EmissiveSettings {
+                    pattern: self.emissive_pattern.clone(),
+                    non_black: self.emissive_non_black,
+                    multiplier: self.emissive_multiplier,
+                }
+            }),
+        }
+    }
+}
+
+/// The clear color and the camera's fog color. Change this rather than either of them so the
+/// fog keeps blending into the sky.
+#[derive(Resource, Clone, Copy)]
+pub struct SkyColor(pub Color);
+
+/// `--sky-color` is multiplied by this for the HDR camera.
+const SKY_BRIGHTNESS: f32 = 3.0;
+
+impl SkyColor {
+    /// The `--sky-color` that gives this sky.
+    pub fn flag_color(&self) -> Color {
+        let color = self.0;
+        Color::rgba(
+            color.r() / SKY_BRIGHTNESS,
+            color.g() / SKY_BRIGHTNESS,
+            color.b() / SKY_BRIGHTNESS,
+            color.a(),
+        )
+    }
+}
+
+/// Applies `SkyColor` to `ClearColor` and the fog whenever it changes.
+fn sync_sky_color(
+    sky_color: Res<SkyColor>,
+    mut clear_color: ResMut<ClearColor>,
+    mut fog: Query<&mut FogSettings>,
+) {
+    if !sky_color.is_changed() {
+        return;
+    }
+    clear_color.0 = sky_color.0;
+    for mut fog in &mut fog {
+        fog.color = sky_color.0;
+    }
+}
+
+fn parse_hex_color(value: &str) -> Result<Color, String> {
+    Color::hex(value).map_err(|e| format!("Invalid hex color {value}: {e}"))
 }
 
 pub fn main() {
@@ -58,61 +531,306 @@ pub fn main() {
 
     if args.convert {
         println!("This will take a few minutes");
-        convert_images_to_ktx2();
-        change_gltf_to_use_ktx2();
+        let gltfs = gltf_paths(&args.scene, &args.convert_gltf);
+        if !convert_images_to_ktx2(&gltfs, args.convert_format) {
+            eprintln!(
+                "Not rewriting the gltf files to use ktx2 since some images failed to convert"
+            );
+            std::process::exit(1);
+        }
+        for gltf in &gltfs {
+            if change_gltf_to_use_ktx2(gltf) {
+                println!("Rewrote {} to use ktx2", gltf.display());
+            }
+        }
+    }
+
+    let lighting_profile = match &args.lighting_profile {
+        Some(path) => LightingProfile::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load lighting profile {path}: {e:#}");
+            std::process::exit(1);
+        }),
+        None => LightingProfile::from_args(&args),
+    };
+
+    if let Some(output) = &args.export_optimized {
+        if let Err(e) = export_optimized(&args.scene, output) {
+            eprintln!("Failed to export {output}: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.validate {
+        run_validation(args);
+        return;
     }
 
     let mut app = App::new();
+    SceneSet::configure(&mut app);
 
     app.insert_resource(args.clone())
         .insert_resource(Msaa::Off)
-        .insert_resource(ClearColor(Color::rgb(0.9 * 3.0, 0.9 * 3.0, 1.0 * 3.0)))
+        .insert_resource(SkyColor(args.sky_color()))
+        .insert_resource(ClearColor(args.sky_color()))
         .insert_resource(AmbientLight {
-            color: Color::rgb(0.0, 0.0, 0.0),
-            brightness: 0.0,
+            color: args.ambient_color.unwrap_or(Color::WHITE),
+            brightness: args.ambient_brightness,
         })
         .insert_resource(WinitSettings {
             focused_mode: UpdateMode::Continuous,
             unfocused_mode: UpdateMode::Continuous,
         })
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                present_mode: PresentMode::Immediate,
-                resolution: WindowResolution::new(1920.0, 1080.0).with_scale_factor_override(1.0),
-                ..default()
-            }),
-            ..default()
-        }))
-        .add_plugins(LogDiagnosticsPlugin::default())
-        .add_plugins(FrameTimeDiagnosticsPlugin::default())
-        // Generating mipmaps takes a minute
-        .insert_resource(MipmapGeneratorSettings {
-            anisotropic_filtering: 16,
-            ..default()
+        .add_plugins(if args.verify_against.is_some() {
+            // Verification renders offscreen, so it runs without a window like --validate
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(args.log_plugin())
+                .disable::<WinitPlugin>()
+        } else {
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        present_mode: PresentMode::Immediate,
+                        resolution: WindowResolution::new(1920.0, 1080.0)
+                            .with_scale_factor_override(1.0),
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .set(args.log_plugin())
         })
-        .add_plugins((
-            MipmapGeneratorPlugin,
-            CameraControllerPlugin,
-            TemporalAntiAliasPlugin,
-        ))
-        // Mipmap generation be skipped if ktx2 is used
+        .add_plugins(LogDiagnosticsPlugin::default())
+        .add_plugins(FrameTimeDiagnosticsPlugin)
+        .add_plugins((CameraControllerPlugin, ImageReadbackPlugin))
         .add_systems(
             Update,
             (
-                generate_mipmaps::<StandardMaterial>,
-                proc_scene,
                 input,
-                benchmark,
+                benchmark.in_set(SceneSet::Benchmark),
+                log_scene_stats,
+            ),
+        )
+        .init_resource::<AoDebugView>()
+        .init_resource::<SelectedLight>()
+        .init_resource::<PhotoMode>()
+        .init_resource::<BenchmarkRunning>()
+        .init_resource::<BenchPositions>()
+        .add_event::<StartBenchmark>()
+        .add_event::<BenchmarkFinished>()
+        .insert_resource(SunTemperature(args.sun_temp))
+        .insert_resource(SunControlSettings {
+            sensitivity: args.sun_drag_sensitivity,
+            smoothing: args.sun_drag_smoothing.clamp(0.0, 1.0),
+        })
+        .insert_resource(args.proc_scene_settings())
+        .add_plugins(ProcScenePlugin)
+        .add_systems(Startup, (setup, spawn_loading_screen))
+        .add_systems(
+            PostStartup,
+            (check_main_camera, capture_initial_state, check_ktx2_support),
+        )
+        .add_systems(
+            Update,
+            (
+                move_directional_light,
+                adjust_environment_map_intensity,
+                blend_environment_maps,
+                apply_environment_maps,
+                check_environment_maps,
+                toggle_ao_debug_view,
+                select_light,
+                adjust_light_intensity,
+                toggle_selected_light,
+                adjust_ambient_light,
+                adjust_sun_temperature,
+                cycle_shadow_map_size,
+                toggle_photo_mode,
+                supersampled_screenshot,
+                switch_quality_preset,
+                inspect_mesh,
+                reset_tweaks,
+                scene_ready.pipe(update_loading_screen),
+                print_render_settings,
             ),
         )
-        .add_systems(Startup, setup)
-        .add_systems(Update, move_directional_light);
+        .add_systems(
+            Update,
+            (
+                toggle_sun_shadows,
+                sync_sky_color,
+                cycle_msaa,
+                set_time_scale,
+                adjust_fov,
+                toggle_orthographic,
+                settled_screenshot,
+                toggle_fps_cap,
+                adjust_cascade_distance,
+            ),
+        )
+        .insert_resource(FpsCap::from_args(&args))
+        .insert_resource(TimeOfDay(
+            args.time_of_day
+                .unwrap_or(lighting_profile.keyframes[0].time),
+        ))
+        .insert_resource(lighting_profile)
+        .add_systems(Update, (adjust_time_of_day, apply_lighting_profile).chain())
+        .add_systems(Last, limit_frame_rate)
+        // Always added since the quality presets can turn TAA on even with --minimal
+        .add_plugins(TemporalAntiAliasPlugin);
+    if args.minimal {
+        info!("TAA is inactive with --minimal until a quality preset enables it");
+    }
 
+    if args.no_mipmaps {
+        // Nothing is ever queued, but the systems waiting for mipmaps before starting need to see
+        // that generation is done
+        app.init_resource::<MipmapTasks<StandardMaterial>>();
+    } else {
+        // Generating mipmaps takes a minute, a few images are handled per frame so the loading
+        // screen keeps updating
+        app.insert_resource(MipmapGeneratorSettings {
+            anisotropic_filtering: 16,
+            max_per_frame: Some(4),
+            ..default()
+        })
+        .add_plugins(MipmapGeneratorPlugin::default().with_material::<StandardMaterial>());
+    }
+    if args.deferred {
+        // Applies to materials left on OpaqueRendererMethod::Auto. The foliage stays forward
+        // since bevy doesn't support diffuse transmission in the deferred path
+        app.insert_resource(DefaultOpaqueRendererMethod::deferred());
+    }
+    if args.dof {
+        #[cfg(feature = "bevy_main")]
+        app.add_systems(
+            Update,
+            (
+                depth_of_field::adjust_depth_of_field,
+                depth_of_field::auto_focus,
+            ),
+        );
+        #[cfg(not(feature = "bevy_main"))]
+        warn!("--dof requires the bevy_main feature, depth of field isn't available in bevy 0.13");
+    }
+    if args.bench_uncapped {
+        app.add_systems(Update, bench_uncapped.after(benchmark));
+    }
+    if let Some(size) = args.shadow_map_size {
+        app.insert_resource(DirectionalLightShadowMap { size });
+    }
+
+    if args.sweep.is_some() {
+        app.add_systems(
+            Update,
+            run_sweep.run_if(scene_ready).before(SceneSet::Benchmark),
+        );
+    }
+
+    if args.debug_bench_path {
+        app.add_systems(Update, draw_bench_path);
+    }
+
+    if args.grid {
+        app.insert_resource(GridGizmo {
+            spacing: args.grid_spacing,
+            extent: args.grid_extent,
+            visible: true,
+        })
+        .add_systems(Update, (toggle_grid, draw_grid).chain());
+    }
+
+    if args.bench_budget_ms.is_some() {
+        app.add_systems(
+            Update,
+            run_bench_budget
+                .run_if(scene_ready)
+                .before(SceneSet::Benchmark),
+        );
+    }
+
+    if args.flythrough.is_some() {
+        app.add_systems(
+            Update,
+            run_flythrough
+                .run_if(scene_ready)
+                .before(SceneSet::Benchmark),
+        );
+    }
+
+    if args.only_node.is_some() {
+        app.add_systems(Update, filter_scene_nodes.before(SceneSet::PostProcess));
+    }
+
+    if args.split_compare.is_some() {
+        app.init_resource::<SplitCompareBench>()
+            .add_systems(PostStartup, setup_split_compare)
+            .add_systems(
+                Update,
+                (
+                    bench_split_compare.before(SceneSet::Benchmark),
+                    update_split_compare,
+                ),
+            );
+    }
+
+    if args.overlay {
+        app.add_systems(Startup, spawn_overlay)
+            .add_systems(Update, update_overlay);
+    }
+
+    if args.log_spikes {
+        app.add_systems(Update, log_frame_spikes);
+    }
+
+    if args.dump_hierarchy {
+        app.add_systems(Update, dump_hierarchy.after(SceneSet::PostProcess));
+    }
+
+    if args.list_cameras || args.list_lights {
+        app.add_systems(Update, list_cameras_and_lights.after(SceneSet::PostProcess));
+    }
+
+    if args.run_seconds.is_some() {
+        app.add_systems(Update, exit_after_run_seconds);
+    }
+
+    if args.verify_against.is_some() {
+        app.add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .add_systems(PostStartup, setup_verify_target)
+        .add_systems(Update, verify_against_references.run_if(scene_ready));
+    }
+    if args.debug_instancing {
+        if !args.instance {
+            warn!("--debug-instancing has nothing to show without --instance");
+        }
+        app.add_systems(
+            Update,
+            (draw_instancing_debug_view, cycle_instancing_filter),
+        );
+    }
+    if args.debug_cascades {
+        app.add_plugins(MaterialPlugin::<CascadeDebugMaterial>::default())
+            .add_systems(Update, toggle_cascade_debug_view);
+    }
     if args.no_frustum_culling {
         app.add_systems(Update, add_no_frustum_culling);
     }
     if args.instance {
-        app.add_plugins((
+        app.insert_resource(AutoInstanceSettings {
+            report_instanced_draws: args.instance_report,
+            warn_unmatched_distance: args.instance_warn_distance,
+            detect_scale: args.instance_detect_scale,
+            instance_by_material: args.instance_by_material,
+            ..default()
+        })
+        .add_plugins((
             AutoInstancePlugin,
             AutoInstanceMaterialPlugin::<StandardMaterial>::default(),
         ));
@@ -122,38 +840,67 @@ pub fn main() {
 }
 
 #[derive(Component)]
-pub struct PostProcScene;
+pub struct GrifLight;
 
+/// The key directional light. Sun specific controls like the temperature, the time of day and
+/// the shadow settings act on this rather than on every `DirectionalLight`.
 #[derive(Component)]
-pub struct GrifLight;
+pub struct Sun;
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<Args>) {
-    println!("Loading models, generating mipmaps");
+/// The camera moved by the `input` and `benchmark` systems, so other cameras can be added freely.
+#[derive(Component)]
+pub struct BenchmarkCamera;
+
+/// Distance along x between copies of the scene spawned with `--duplicate-scene`
+const DUPLICATE_SCENE_SPACING: f32 = 40.0;
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    args: Res<Args>,
+    sky_color: Res<SkyColor>,
+) {
+    if args.no_mipmaps {
+        info!("Loading models");
+    } else {
+        info!("Loading models, generating mipmaps");
+    }
+    info!(
+        "Opaque render path: {}",
+        if args.deferred { "deferred" } else { "forward" }
+    );
 
     // Hidden Alley
-    commands.spawn((
-        SceneBundle {
-            scene: asset_server.load("hidden_alley/ph_hidden_alley_bevy_bake.gltf#Scene0"),
-            transform: Transform::from_xyz(-18.0, 0.0, 0.0),
-            ..default()
-        },
-        PostProcScene,
-        AutoInstanceMaterialRecursive,
-        AutoInstanceMeshRecursive,
-    ));
+    let scene = asset_server.load(format!("{}#Scene0", args.scene));
+    for i in 0..args.duplicate_scene.max(1) {
+        commands.spawn((
+            SceneBundle {
+                scene: scene.clone(),
+                transform: Transform::from_xyz(
+                    -18.0 + i as f32 * DUPLICATE_SCENE_SPACING,
+                    0.0,
+                    0.0,
+                ),
+                ..default()
+            },
+            PostProcScene,
+            AutoInstanceMaterialRecursive,
+            AutoInstanceMeshRecursive,
+        ));
+    }
 
     // Sun
     commands.spawn((
         DirectionalLightBundle {
             transform: Transform::from_rotation(Quat::from_euler(
                 EulerRot::XYZ,
-                -1.8327503,
-                -0.41924718,
-                0.0,
+                SUN_ROTATION.0,
+                SUN_ROTATION.1,
+                SUN_ROTATION.2,
             )),
             directional_light: DirectionalLight {
-                color: Color::rgb_linear(0.95, 0.69268, 0.537758),
-                illuminance: 3000000.0 * 0.2,
+                color: sun_color(args.sun_temp),
+                illuminance: SUN_ILLUMINANCE,
                 shadows_enabled: !args.minimal,
                 shadow_depth_bias: 0.04,
                 shadow_normal_bias: 1.8,
@@ -167,8 +914,44 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
             ..default()
         },
         GrifLight,
+        Sun,
+        Name::new("Sun"),
     ));
 
+    if args.three_point {
+        // Unshadowed fill from the camera's side and rim from behind the alley, relative to the
+        // sun as the key light
+        for (name, direction, color, illuminance) in [
+            (
+                "Fill",
+                Vec3::new(0.5, -0.4, -1.0),
+                Color::rgb(0.8, 0.88, 1.0),
+                SUN_ILLUMINANCE * 0.25,
+            ),
+            (
+                "Rim",
+                Vec3::new(-0.3, -0.6, 1.0),
+                Color::rgb(1.0, 0.95, 0.9),
+                SUN_ILLUMINANCE * 0.5,
+            ),
+        ] {
+            commands.spawn((
+                DirectionalLightBundle {
+                    transform: Transform::default().looking_to(direction, Vec3::Y),
+                    directional_light: DirectionalLight {
+                        color,
+                        illuminance,
+                        shadows_enabled: false,
+                        ..default()
+                    },
+                    ..default()
+                },
+                GrifLight,
+                Name::new(name),
+            ));
+        }
+    }
+
     let point_spot_mult = 1000.0;
 
     // Sky
@@ -186,6 +969,7 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
             ..default()
         },
         GrifLight,
+        Name::new("Sky"),
     ));
 
     // Sun Refl
@@ -205,8 +989,11 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
             ..default()
         },
         GrifLight,
+        Name::new("Sun Refl"),
     ));
 
+    let environment_maps = EnvironmentMaps::from_args(&args, &asset_server);
+
     // Camera
     let mut cam = commands.spawn((
         Camera3dBundle {
@@ -217,7 +1004,10 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
             transform: Transform::from_xyz(-17.68169, 0.7696594, 4.23056)
                 .looking_at(Vec3::new(-20.0, 3.5, -10.0), Vec3::Y),
             projection: Projection::Perspective(PerspectiveProjection {
-                fov: std::f32::consts::PI / 3.0,
+                fov: args
+                    .fov
+                    .clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES)
+                    .to_radians(),
                 ..default()
             }),
             color_grading: ColorGrading {
@@ -232,11 +1022,8 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
             },
             ..default()
         },
-        EnvironmentMapLight {
-            diffuse_map: asset_server.load("environment_maps/pisa_diffuse_rgb9e5_zstd.ktx2"),
-            specular_map: asset_server.load("environment_maps/pisa_specular_rgb9e5_zstd.ktx2"),
-            intensity: 1000.0,
-        },
+        environment_maps.light(),
+        BenchmarkCamera,
         CameraController {
             walk_speed: 2.0,
             mouse_key_enable_mouse: MouseButton::Right,
@@ -245,14 +1032,18 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
         .print_controls(),
     ));
 
+    if args.deferred {
+        cam.insert((DepthPrepass, DeferredPrepass));
+    }
+    #[cfg(feature = "bevy_main")]
+    if args.dof {
+        cam.insert(depth_of_field::dof_settings());
+    }
     if !args.minimal {
         cam.insert((
-            BloomSettings {
-                intensity: 0.04,
-                ..default()
-            },
+            scene_bloom(),
             FogSettings {
-                color: Color::rgb(0.9 * 3.0, 0.9 * 3.0, 1.0 * 3.0),
+                color: sky_color.0,
                 falloff: FogFalloff::Linear {
                     start: 4.0,
                     end: 500.0,
@@ -263,93 +1054,75 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<A
         ))
         .insert(ScreenSpaceAmbientOcclusionBundle::default());
     }
+    commands.insert_resource(environment_maps);
 }
 
-pub fn all_children<F: FnMut(Entity)>(
-    children: &Children,
-    children_query: &Query<&Children>,
-    closure: &mut F,
-) {
-    for child in children {
-        if let Ok(children) = children_query.get(*child) {
-            all_children(children, children_query, closure);
-        }
-        closure(*child);
+pub fn scene_bloom() -> BloomSettings {
+    BloomSettings {
+        intensity: 0.04,
+        ..default()
     }
 }
 
-#[allow(clippy::type_complexity)]
-pub fn proc_scene(
-    mut commands: Commands,
-    materials_query: Query<Entity, With<PostProcScene>>,
-    children_query: Query<&Children>,
-    has_std_mat: Query<&Handle<StandardMaterial>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    lights: Query<
-        Entity,
-        (
-            Or<(With<PointLight>, With<DirectionalLight>, With<SpotLight>)>,
-            Without<GrifLight>,
-        ),
-    >,
-    cameras: Query<Entity, With<Camera>>,
-) {
-    for entity in materials_query.iter() {
-        if let Ok(children) = children_query.get(entity) {
-            all_children(children, &children_query, &mut |entity| {
-                if let Ok(mat_h) = has_std_mat.get(entity) {
-                    if let Some(mat) = materials.get_mut(mat_h) {
-                        match mat.alpha_mode {
-                            AlphaMode::Mask(_) => {
-                                mat.diffuse_transmission = 0.6;
-                                mat.double_sided = true;
-                                mat.cull_mode = None;
-                                mat.thickness = 0.2;
-                                commands.entity(entity).insert(TransmittedShadowReceiver);
-                            }
-                            _ => (),
-                        }
-                    }
-                }
-
-                // Remove Default Lights
-                if lights.get(entity).is_ok() {
-                    commands.entity(entity).despawn_recursive();
-                }
-
-                // Remove Default Cameras
-                if cameras.get(entity).is_ok() {
-                    commands.entity(entity).despawn_recursive();
-                }
-            });
-            commands.entity(entity).remove::<PostProcScene>();
-        }
-    }
+/// How dragging the sun with L follows the mouse, from `--sun-drag-sensitivity` and
+/// `--sun-drag-smoothing`.
+#[derive(Resource, Clone, Copy)]
+pub struct SunControlSettings {
+    /// Degrees of rotation per pixel of mouse movement
+    pub sensitivity: f32,
+    /// Fraction of the way to the dragged rotation the sun moves each frame
+    pub smoothing: f32,
 }
+
+/// Hold L and move the mouse to rotate the selected directional light, or the sun if the
+/// selected light isn't one.
 fn move_directional_light(
-    mut query: Query<&mut Transform, With<DirectionalLight>>,
+    mut query: Query<(&mut Transform, Option<&Name>), With<DirectionalLight>>,
+    sun: Query<Entity, With<Sun>>,
+    selected: Res<SelectedLight>,
     mut motion_evr: EventReader<MouseMotion>,
     keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<SunControlSettings>,
     mut e_rot: Local<Vec3>,
 ) {
+    let Some(entity) = selected
+        .0
+        .filter(|entity| query.contains(*entity))
+        .or_else(|| sun.get_single().ok())
+    else {
+        return;
+    };
+    let Ok((mut trans, name)) = query.get_mut(entity) else {
+        return;
+    };
+    let euler = trans.rotation.to_euler(EulerRot::XYZ);
+    let euler = vec3(euler.0, euler.1, euler.2);
+    // Euler XYZ in radians, like sun_rotation in a lighting profile
+    if keys.just_released(KeyCode::KeyL) {
+        info!(
+            "{} rotation: ({}, {}, {})",
+            light_label(entity, name),
+            euler.x,
+            euler.y,
+            euler.z
+        );
+    }
+    if keys.just_pressed(KeyCode::KeyL) {
+        // Start from this light's rotation, not the last light that was dragged
+        *e_rot = euler;
+    }
     if !keys.pressed(KeyCode::KeyL) {
         return;
     }
-    for mut trans in &mut query {
-        let euler = trans.rotation.to_euler(EulerRot::XYZ);
-        let euler = vec3(euler.0, euler.1, euler.2);
-
-        for ev in motion_evr.read() {
-            *e_rot = vec3(
-                (euler.x.to_degrees() + ev.delta.y * 2.0).to_radians(),
-                (euler.y.to_degrees() + ev.delta.x * 2.0).to_radians(),
-                euler.z,
-            );
-        }
-        let store = euler.lerp(*e_rot, 0.2);
-        dbg!(store.x, store.y, store.z);
-        trans.rotation = Quat::from_euler(EulerRot::XYZ, store.x, store.y, store.z);
+    for ev in motion_evr.read() {
+        *e_rot = vec3(
+            (euler.x.to_degrees() + ev.delta.y * settings.sensitivity).to_radians(),
+            (euler.y.to_degrees() + ev.delta.x * settings.sensitivity).to_radians(),
+            euler.z,
+        );
     }
+    let store = euler.lerp(*e_rot, settings.smoothing);
+    trans.rotation = Quat::from_euler(EulerRot::XYZ, store.x, store.y, store.z);
 }
 
 const CAM_POS_1: Transform = Transform {
@@ -370,65 +1143,408 @@ const CAM_POS_3: Transform = Transform {
     scale: Vec3::ONE,
 };
 
-fn input(input: Res<ButtonInput<KeyCode>>, mut camera: Query<&mut Transform, With<Camera>>) {
-    let Ok(mut transform) = camera.get_single_mut() else {
+/// Checks that setup spawned the camera the input and benchmark systems expect.
+fn check_main_camera(cameras: Query<Entity, (With<Camera>, With<BenchmarkCamera>)>) {
+    let count = cameras.iter().count();
+    assert_eq!(
+        count, 1,
+        "Expected exactly one Camera with a BenchmarkCamera, found {count}. \
+        The input and benchmark systems need a single main camera."
+    );
+}
+
+/// Exits once `--run-seconds` have passed. A panicking system already exits with an error, so
+/// this only needs to check that the scene spawned.
+fn exit_after_run_seconds(
+    time: Res<Time>,
+    args: Res<Args>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(run_seconds) = args.run_seconds else {
         return;
     };
+    if time.elapsed_seconds() < run_seconds {
+        return;
+    }
+    if !loading_scenes.is_empty() {
+        panic!("{} didn't load within {run_seconds} seconds", args.scene);
+    }
+    info!("Ran for {run_seconds} seconds, exiting");
+    exit.send(AppExit);
+}
+
+fn warn_missing_camera(warned: &mut bool, system: &str, error: QuerySingleError) {
+    if let QuerySingleError::NoEntities(_) = error {
+        if !*warned {
+            warn!("{system}: no BenchmarkCamera found, was it despawned? {system} will do nothing until one exists.");
+            *warned = true;
+        }
+    }
+}
+
+/// Eases between two camera transforms, `t` goes from 0 to 1.
+fn ease_transform(from: &Transform, to: &Transform, t: f32) -> Transform {
+    let t = t.clamp(0.0, 1.0);
+    let t = t * t * (3.0 - 2.0 * t);
+    Transform {
+        translation: from.translation.lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t),
+        scale: from.scale.lerp(to.scale, t),
+    }
+}
+
+/// A camera move to one of the `CAM_POS_*` presets started at `started` seconds.
+struct CameraTransition {
+    from: Transform,
+    to: Transform,
+    started: f32,
+}
+
+fn input(
+    input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    args: Res<Args>,
+    running: Res<BenchmarkRunning>,
+    mut camera: Query<&mut Transform, With<BenchmarkCamera>>,
+    mut transition: Local<Option<CameraTransition>>,
+    mut warned: Local<bool>,
+) {
+    let mut transform = match camera.get_single_mut() {
+        Ok(transform) => transform,
+        Err(e) => {
+            warn_missing_camera(&mut warned, "input", e);
+            return;
+        }
+    };
     if input.just_pressed(KeyCode::KeyI) {
         info!("{:?}", transform);
     }
-    if input.just_pressed(KeyCode::Digit1) {
-        *transform = CAM_POS_1
+    let target = [
+        (KeyCode::Digit1, CAM_POS_1),
+        (KeyCode::Digit2, CAM_POS_2),
+        (KeyCode::Digit3, CAM_POS_3),
+    ]
+    .into_iter()
+    .find(|(key, _)| input.just_pressed(*key))
+    .map(|(_, target)| target);
+    if let Some(target) = target {
+        let instant = args.camera_transition <= 0.0
+            || input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        if instant {
+            *transform = target;
+            *transition = None;
+        } else {
+            *transition = Some(CameraTransition {
+                from: *transform,
+                to: target,
+                started: time.elapsed_seconds(),
+            });
+        }
+    }
+
+    // The benchmark moves the camera itself
+    if running.0 {
+        *transition = None;
     }
-    if input.just_pressed(KeyCode::Digit2) {
-        *transform = CAM_POS_2
+    if let Some(current) = &*transition {
+        let t = (time.elapsed_seconds() - current.started) / args.camera_transition;
+        *transform = ease_transform(&current.from, &current.to, t);
+        if t >= 1.0 {
+            *transition = None;
+        }
     }
-    if input.just_pressed(KeyCode::Digit3) {
-        *transform = CAM_POS_3
+}
+
+/// Progress through one pass over the benchmark camera positions.
+struct BenchmarkPass {
+    step: usize,
+    step_started: Instant,
+    /// Frames rendered at the current step, including warmup
+    step_frames: u32,
+    /// Measured frame times of the current step
+    step_times: Vec<f32>,
+    step_frame_times: Vec<f32>,
+    last_frame: Instant,
+    /// Measured frame times of every finished step, without warmup and cooldown frames
+    frame_times: Vec<f32>,
+}
+
+struct PassResult {
+    frame_time: f32,
+    step_frame_times: Vec<f32>,
+    frame_times: Vec<f32>,
+}
+
+impl BenchmarkPass {
+    fn new() -> Self {
+        let now = Instant::now();
+        BenchmarkPass {
+            step: 0,
+            step_started: now,
+            step_frames: 0,
+            step_times: Vec::new(),
+            step_frame_times: Vec::new(),
+            last_frame: now,
+            frame_times: Vec::new(),
+        }
     }
 }
 
+/// The camera positions the benchmark visits in order. Add to the list to measure more
+/// viewpoints, every position is one step.
+#[derive(Resource, Clone)]
+pub struct BenchPositions(pub Vec<Transform>);
+
+impl Default for BenchPositions {
+    fn default() -> Self {
+        BenchPositions(vec![CAM_POS_1, CAM_POS_2, CAM_POS_3])
+    }
+}
+
+/// Whether the benchmark is currently moving the camera.
+#[derive(Resource, Default)]
+pub struct BenchmarkRunning(pub bool);
+
+/// Starts the benchmark like pressing B.
+#[derive(Event)]
+pub struct StartBenchmark;
+
+/// Sent when every benchmark pass has finished.
+#[derive(Event)]
+pub struct BenchmarkFinished {
+    pub avg_frame_time_ms: f32,
+    pub p99_frame_time_ms: f32,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn benchmark(
     input: Res<ButtonInput<KeyCode>>,
-    mut camera: Query<&mut Transform, With<Camera>>,
-    mut bench_started: Local<Option<Instant>>,
-    mut bench_frame: Local<u32>,
-    mut count_per_step: Local<u32>,
-    time: Res<Time>,
+    mut camera: Query<&mut Transform, With<BenchmarkCamera>>,
+    mut pass: Local<Option<BenchmarkPass>>,
+    mut pass_results: Local<Vec<PassResult>>,
+    mut warned: Local<bool>,
+    mut running: ResMut<BenchmarkRunning>,
+    mut start: EventReader<StartBenchmark>,
+    mut finished: EventWriter<BenchmarkFinished>,
+    args: Res<Args>,
+    mut peak_memory: Local<PeakMemory>,
+    meshes: Res<Assets<Mesh>>,
+    images: Res<Assets<Image>>,
+    positions: Res<BenchPositions>,
 ) {
-    if input.just_pressed(KeyCode::KeyB) && bench_started.is_none() {
-        *bench_started = Some(Instant::now());
-        *bench_frame = 0;
-        // Try to render for around 2s or at least 30 frames per step
-        *count_per_step = ((2.0 / time.delta_seconds()) as u32).max(30);
+    let start_requested = start.read().count() > 0;
+    let toggled = input.just_pressed(KeyCode::KeyB);
+    if pass.is_none() && (start_requested || toggled) {
+        if positions.0.is_empty() {
+            warn!("BenchPositions is empty, there's nothing to benchmark");
+            return;
+        }
+        *pass = Some(BenchmarkPass::new());
+        running.0 = true;
+        pass_results.clear();
+        *peak_memory = PeakMemory::default();
+        info!("Starting Benchmark with {}s per step", args.bench_seconds);
+    } else if toggled && args.bench_loop == 0 {
+        finished.send_batch(finish_benchmark(&pass_results, &args));
+        print_memory_summary(&peak_memory, &meshes, &images);
+        *pass = None;
+        running.0 = false;
+        return;
+    }
+    let Some(current) = pass.as_mut() else {
+        return;
+    };
+    let mut transform = match camera.get_single_mut() {
+        Ok(transform) => transform,
+        Err(e) => {
+            warn_missing_camera(&mut warned, "benchmark", e);
+            return;
+        }
+    };
+    if current.step_frames == 0 {
+        *transform = positions.0[current.step];
+    }
+    current.step_frames += 1;
+    let now = Instant::now();
+    let frame_time = (now - current.last_frame).as_secs_f32() * 1000.0;
+    current.last_frame = now;
+    // Pipelines are specialized lazily, so the first frames at each position include
+    // compilation stalls. The step's time starts once they're done.
+    if current.step_frames <= args.bench_warmup {
+        current.step_started = now;
+        return;
+    }
+    current.step_times.push(frame_time);
+
+    // Steps are timed rather than counted in frames so every machine measures for the same duration
+    let step_elapsed = current.step_started.elapsed().as_secs_f32();
+    if step_elapsed < args.bench_seconds {
+        return;
+    }
+    let measured = current
+        .step_times
+        .len()
+        .saturating_sub(args.bench_cooldown as usize)
+        .max(1);
+    current.step_times.truncate(measured);
+    info!(
+        "Benchmark step {}: {:.2}s, {} frames, {} measured",
+        current.step + 1,
+        step_elapsed,
+        current.step_frames,
+        measured
+    );
+    let step_frame_time = current.step_times.iter().sum::<f32>() / measured as f32;
+    current.step_frame_times.push(step_frame_time);
+    let step_times = std::mem::take(&mut current.step_times);
+    current.frame_times.extend(step_times);
+    current.step += 1;
+    current.step_frames = 0;
+    current.step_started = Instant::now();
+    // Sampled once per step rather than every frame to keep it out of the measurement
+    peak_memory.sample();
+    if current.step < positions.0.len() {
+        return;
+    }
+
+    let frame_time = current.frame_times.iter().sum::<f32>() / current.frame_times.len() as f32;
+    pass_results.push(PassResult {
+        frame_time,
+        step_frame_times: std::mem::take(&mut current.step_frame_times),
+        frame_times: std::mem::take(&mut current.frame_times),
+    });
+    if args.bench_loop == 1 {
+        println!("Benchmark avg cpu frame time: {:.2}ms", frame_time);
+    } else {
+        let rolling_avg =
+            pass_results.iter().map(|r| r.frame_time).sum::<f32>() / pass_results.len() as f32;
         println!(
-            "Starting Benchmark with {} frames per step",
-            *count_per_step
+            "Benchmark pass {} avg cpu frame time: {:.2}ms, rolling avg: {:.2}ms",
+            pass_results.len(),
+            frame_time,
+            rolling_avg
         );
     }
-    if bench_started.is_none() {
-        return;
+    if args.bench_loop == 0 || pass_results.len() < args.bench_loop as usize {
+        *pass = Some(BenchmarkPass::new());
+    } else {
+        finished.send_batch(finish_benchmark(&pass_results, &args));
+        print_memory_summary(&peak_memory, &meshes, &images);
+        *pass = None;
+        running.0 = false;
     }
-    let Ok(mut transform) = camera.get_single_mut() else {
+}
+
+/// Settings replaced while an uncapped benchmark runs, restored once it finishes.
+struct UncappedRestore {
+    present_mode: PresentMode,
+    winit_settings: WinitSettings,
+    time_update: TimeUpdateStrategy,
+}
+
+/// With `--bench-uncapped`, the benchmark renders as fast as possible with vsync off, and time
+/// advances by a fixed step each frame so anything time based doesn't depend on frame pacing.
+fn bench_uncapped(
+    running: Res<BenchmarkRunning>,
+    mut restore: Local<Option<UncappedRestore>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut winit_settings: ResMut<WinitSettings>,
+    mut time_update: ResMut<TimeUpdateStrategy>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
         return;
     };
-    if *bench_frame == 0 {
-        *transform = CAM_POS_1
-    } else if *bench_frame == *count_per_step {
-        *transform = CAM_POS_2
-    } else if *bench_frame == *count_per_step * 2 {
-        *transform = CAM_POS_3
-    } else if *bench_frame == *count_per_step * 3 {
-        let elapsed = bench_started.unwrap().elapsed().as_secs_f32();
+    if running.0 && restore.is_none() {
+        *restore = Some(UncappedRestore {
+            present_mode: std::mem::replace(&mut window.present_mode, PresentMode::AutoNoVsync),
+            winit_settings: std::mem::replace(
+                &mut *winit_settings,
+                WinitSettings {
+                    focused_mode: UpdateMode::Continuous,
+                    unfocused_mode: UpdateMode::Continuous,
+                },
+            ),
+            time_update: std::mem::replace(
+                &mut *time_update,
+                TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / 60.0)),
+            ),
+        });
+        info!("Benchmark running uncapped");
+    } else if !running.0 {
+        if let Some(original) = restore.take() {
+            window.present_mode = original.present_mode;
+            *winit_settings = original.winit_settings;
+            *time_update = original.time_update;
+        }
+    }
+}
+
+/// Prints the summary, compares against `--bench-baseline` and saves the results.
+fn finish_benchmark(pass_results: &[PassResult], args: &Args) -> Option<BenchmarkFinished> {
+    if pass_results.is_empty() {
+        return None;
+    }
+    let frame_times = pass_results.iter().map(|r| r.frame_time);
+    let min = frame_times.clone().fold(f32::MAX, f32::min);
+    let max = frame_times.clone().fold(f32::MIN, f32::max);
+    let avg = frame_times.sum::<f32>() / pass_results.len() as f32;
+    let mut all_frame_times = pass_results
+        .iter()
+        .flat_map(|r| r.frame_times.iter().copied())
+        .collect::<Vec<_>>();
+    all_frame_times.sort_by(f32::total_cmp);
+    let p99 = all_frame_times
+        .get(all_frame_times.len() * 99 / 100)
+        .copied()
+        .unwrap_or_default();
+    println!("Benchmark p99 cpu frame time: {:.2}ms", p99);
+    if pass_results.len() > 1 {
         println!(
-            "Benchmark avg cpu frame time: {:.2}ms",
-            (elapsed / *bench_frame as f32) * 1000.0
+            "Benchmark finished {} passes, avg cpu frame time: {:.2}ms (min {:.2}ms, max {:.2}ms)",
+            pass_results.len(),
+            avg,
+            min,
+            max
         );
-        *bench_started = None;
-        *bench_frame = 0;
-        *transform = CAM_POS_1;
     }
-    *bench_frame += 1;
+
+    let results = BenchmarkResults {
+        passes: pass_results.len(),
+        avg_frame_time_ms: avg,
+        p99_frame_time_ms: p99,
+        step_frame_times_ms: (0..pass_results[0].step_frame_times.len())
+            .map(|step| {
+                pass_results
+                    .iter()
+                    .map(|r| r.step_frame_times[step])
+                    .sum::<f32>()
+                    / pass_results.len() as f32
+            })
+            .collect(),
+    };
+    // Compare before saving since the baseline may be the previous results file
+    if let Some(baseline_path) = &args.bench_baseline {
+        match BenchmarkResults::load(baseline_path) {
+            Ok(baseline) => {
+                if !results.compare(&baseline, args.bench_tolerance) {
+                    println!(
+                        "Benchmark regressed by more than {}% against {}",
+                        args.bench_tolerance, baseline_path
+                    );
+                }
+            }
+            Err(e) => warn!("Failed to load benchmark baseline {}: {}", baseline_path, e),
+        }
+    }
+    match results.save(BENCHMARK_RESULTS_PATH) {
+        Ok(_) => println!("Saved benchmark results to {}", BENCHMARK_RESULTS_PATH),
+        Err(e) => warn!("Failed to save {}: {}", BENCHMARK_RESULTS_PATH, e),
+    }
+    Some(BenchmarkFinished {
+        avg_frame_time_ms: avg,
+        p99_frame_time_ms: p99,
+    })
 }
 
 pub fn add_no_frustum_culling(
@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+
+/// Resident set size of this process, only implemented on Linux.
+pub fn process_rss_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+        Some(kb * 1024)
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Estimate of the GPU memory used by mesh and image assets, from the size of their data.
+pub fn asset_bytes(meshes: &Assets<Mesh>, images: &Assets<Image>) -> (usize, usize) {
+    let mesh_bytes = meshes
+        .iter()
+        .map(|(_, mesh)| {
+            let attributes = mesh
+                .attributes()
+                .map(|(_, values)| values.get_bytes().len())
+                .sum::<usize>();
+            attributes + mesh.get_index_buffer_bytes().map_or(0, |bytes| bytes.len())
+        })
+        .sum();
+    let image_bytes = images.iter().map(|(_, image)| image.data.len()).sum();
+    (mesh_bytes, image_bytes)
+}
+
+/// Peak process memory seen during a benchmark run.
+#[derive(Default)]
+pub struct PeakMemory {
+    pub rss_bytes: Option<u64>,
+}
+
+impl PeakMemory {
+    pub fn sample(&mut self) {
+        if let Some(rss) = process_rss_bytes() {
+            self.rss_bytes = Some(self.rss_bytes.map_or(rss, |peak| peak.max(rss)));
+        }
+    }
+}
+
+fn mb(bytes: usize) -> f32 {
+    bytes as f32 / (1024.0 * 1024.0)
+}
+
+pub fn print_memory_summary(peak: &PeakMemory, meshes: &Assets<Mesh>, images: &Assets<Image>) {
+    let (mesh_bytes, image_bytes) = asset_bytes(meshes, images);
+    match peak.rss_bytes {
+        Some(rss) => println!("Benchmark peak RSS: {:.1}MB", mb(rss as usize)),
+        None => println!("Benchmark peak RSS: unavailable on this platform"),
+    }
+    println!(
+        "Estimated GPU asset memory: {:.1}MB ({:.1}MB meshes, {:.1}MB images)",
+        mb(mesh_bytes + image_bytes),
+        mb(mesh_bytes),
+        mb(image_bytes)
+    );
+}
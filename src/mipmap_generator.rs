@@ -15,6 +15,8 @@ use bevy::{
 use futures_lite::future;
 use image::{imageops::FilterType, DynamicImage, ImageBuffer};
 
+use crate::schedule::SceneSet;
+
 #[derive(Resource, Deref)]
 pub struct DefaultSampler(ImageSamplerDescriptor);
 
@@ -24,6 +26,23 @@ pub struct MipmapGeneratorSettings {
     pub anisotropic_filtering: u16,
     pub filter_type: FilterType,
     pub minimum_mip_resolution: u32,
+    /// Caps the mip chain at this many levels, including the full resolution level.
+    /// `None` generates the full chain down to `minimum_mip_resolution`.
+    pub max_mip_levels: Option<u32>,
+    /// Images whose asset path contains any of these strings are left at a single mip level.
+    pub exclude_paths: Vec<String>,
+    /// Limits how many images start generating and how many finished images are applied each
+    /// frame, spreading the image copies and uploads over several frames so the window stays
+    /// responsive. `None` handles every image in the frame it's ready.
+    pub max_per_frame: Option<usize>,
+}
+
+impl MipmapGeneratorSettings {
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude_paths
+            .iter()
+            .any(|pattern| path.contains(pattern.as_str()))
+    }
 }
 
 ///Mipmaps will not be generated for materials found on entities that also have the `NoMipmapGeneration` component.
@@ -37,25 +56,97 @@ impl Default for MipmapGeneratorSettings {
             anisotropic_filtering: 8,
             filter_type: FilterType::Triangle,
             minimum_mip_resolution: 1,
+            max_mip_levels: None,
+            exclude_paths: Vec::new(),
+            max_per_frame: None,
         }
     }
 }
 
-pub struct MipmapGeneratorPlugin;
+/// Generates mipmaps for the images of every material type registered with
+/// [`MipmapGeneratorPlugin::with_material`].
+#[derive(Default)]
+pub struct MipmapGeneratorPlugin {
+    materials: Vec<fn(&mut App)>,
+}
+
+impl MipmapGeneratorPlugin {
+    pub fn with_material<M: Material + GetImages>(mut self) -> Self {
+        self.materials.push(|app| {
+            app.add_systems(Update, generate_mipmaps::<M>.in_set(SceneSet::Mipmaps));
+        });
+        self
+    }
+}
+
 impl Plugin for MipmapGeneratorPlugin {
     fn build(&self, app: &mut App) {
         if let Some(image_plugin) = app.get_added_plugins::<ImagePlugin>().first() {
             let default_sampler = image_plugin.default_sampler.clone();
             app.insert_resource(DefaultSampler(default_sampler))
-                .init_resource::<MipmapGeneratorSettings>();
+                .init_resource::<MipmapGeneratorSettings>()
+                .init_resource::<MipmapReport>();
+            for add_material in &self.materials {
+                add_material(app);
+            }
         } else {
             warn!("No ImagePlugin found. Try adding MipmapGeneratorPlugin after DefaultPlugins");
         }
     }
 }
 
+/// Images waiting for their mipmaps. The task is `None` while the image is queued behind
+/// [`MipmapGeneratorSettings::max_per_frame`].
 #[derive(Resource, Default, Deref, DerefMut)]
-pub struct MipmapTasks<M: Material + GetImages>(HashMap<Handle<Image>, (Task<Image>, Handle<M>)>);
+pub struct MipmapTasks<M: Material + GetImages>(HashMap<Handle<Image>, MipmapTask<M>>);
+
+/// The generation task, if it was started, and the material the image was found on.
+pub type MipmapTask<M> = (Option<Task<anyhow::Result<Image>>>, Handle<M>);
+
+/// How many images got mipmaps and which were left without them, logged each time the queue
+/// empties. Images that already have mipmaps or match
+/// [`MipmapGeneratorSettings::exclude_paths`] aren't counted.
+#[derive(Resource, Default)]
+pub struct MipmapReport {
+    pub generated: usize,
+    /// The name of each image that couldn't get mipmaps and why
+    pub skipped: HashMap<AssetId<Image>, (String, String)>,
+    /// Set when the counts changed since they were last logged
+    changed: bool,
+}
+
+impl MipmapReport {
+    fn skip(&mut self, asset_server: &AssetServer, image: &Handle<Image>, reason: String) {
+        let name = asset_server
+            .get_path(image)
+            .map_or_else(|| format!("{:?}", image.id()), |path| path.to_string());
+        // Skipping an image again, e.g. once per material using it, isn't a change
+        if self.skipped.insert(image.id(), (name, reason)).is_none() {
+            self.changed = true;
+        }
+    }
+
+    fn log(&mut self) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        if self.skipped.is_empty() {
+            info!("Mipmaps: generated {}, skipped 0", self.generated);
+            return;
+        }
+        warn!(
+            "Mipmaps: generated {}, skipped {}. These render without mipmaps and may alias:",
+            self.generated,
+            self.skipped.len()
+        );
+        let mut skipped = self.skipped.values().collect::<Vec<_>>();
+        skipped.sort();
+        for (name, reason) in skipped {
+            warn!("  {name}: {reason}");
+        }
+    }
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn generate_mipmaps<M: Material + GetImages>(
@@ -66,7 +157,9 @@ pub fn generate_mipmaps<M: Material + GetImages>(
     mut images: ResMut<Assets<Image>>,
     default_sampler: Res<DefaultSampler>,
     settings: Res<MipmapGeneratorSettings>,
+    asset_server: Res<AssetServer>,
     mut tasks_res: Option<ResMut<MipmapTasks<M>>>,
+    mut report: ResMut<MipmapReport>,
 ) {
     let mut new_tasks = MipmapTasks(HashMap::new());
 
@@ -102,42 +195,79 @@ pub fn generate_mipmaps<M: Material + GetImages>(
                     };
                     descriptor.anisotropy_clamp = settings.anisotropic_filtering;
                     image.sampler = ImageSampler::Descriptor(descriptor);
-                    if image.texture_descriptor.mip_level_count == 1
-                        && check_image_compatible(image).is_ok()
-                    {
-                        let mut image = image.clone();
-                        let settings = settings.clone();
-                        let task = thread_pool.spawn(async move {
-                            match generate_mips_texture(&mut image, &settings.clone()) {
-                                Ok(_) => (),
-                                Err(e) => warn!("{}", e),
+                    let excluded = asset_server
+                        .get_path(image_h)
+                        .is_some_and(|path| settings.is_excluded(&path.to_string()));
+                    if image.texture_descriptor.mip_level_count == 1 && !excluded {
+                        match check_image_compatible(image) {
+                            Ok(()) => {
+                                tasks.insert(image_h.clone(), (None, Handle::Weak(*material_h)));
                             }
-                            image
-                        });
-                        tasks.insert(image_h.clone(), (task, Handle::Weak(material_h.clone())));
+                            Err(e) => report.skip(&asset_server, image_h, e.to_string()),
+                        }
                     }
                 }
             }
         }
     }
 
+    let max_per_frame = settings.max_per_frame.unwrap_or(usize::MAX);
     let mut completed = Vec::new();
+    let mut started = 0;
+    for (image_h, (task, _)) in tasks.iter_mut() {
+        if started == max_per_frame {
+            break;
+        }
+        if task.is_some() {
+            continue;
+        }
+        let Some(image) = images.get(image_h) else {
+            // The image was removed before its turn came
+            completed.push(image_h.clone());
+            continue;
+        };
+        let mut image = image.clone();
+        let settings = settings.clone();
+        *task = Some(
+            thread_pool
+                .spawn(async move { generate_mips_texture(&mut image, &settings).map(|_| image) }),
+        );
+        started += 1;
+    }
 
-    for (image_h, inner) in tasks.iter_mut() {
-        // TODO couldn't get &mut in destructure to work correctly for (task, material_h)
-        if let Some(new_image) = future::block_on(future::poll_once(&mut inner.0)) {
-            if let Some(image) = images.get_mut(image_h) {
-                *image = new_image;
+    let mut applied = 0;
+    for (image_h, (task, material_h)) in tasks.iter_mut() {
+        // Stop polling once the budget is used up, a ready task's image would be lost otherwise
+        if applied == max_per_frame {
+            break;
+        }
+        let Some(task) = task else {
+            continue;
+        };
+        if let Some(result) = future::block_on(future::poll_once(task)) {
+            match result {
+                Ok(new_image) => {
+                    if let Some(image) = images.get_mut(image_h) {
+                        *image = new_image;
+                    }
+                    // Touch material to trigger change detection
+                    let _ = materials.get_mut(&*material_h);
+                    report.generated += 1;
+                    report.changed = true;
+                }
+                Err(e) => report.skip(&asset_server, image_h, e.to_string()),
             }
-            // Touch material to trigger change detection
-            let _ = materials.get_mut(&inner.1);
             completed.push(image_h.clone());
+            applied += 1;
         }
     }
 
     for image_h in completed {
         tasks.remove(&image_h);
     }
+    if tasks.is_empty() {
+        report.log();
+    }
 
     if tasks_res.is_none() {
         commands.insert_resource(new_tasks);
@@ -154,7 +284,7 @@ pub fn generate_mips_texture(
             let (mip_level_count, image_data) = generate_mips(
                 &mut dyn_image,
                 settings.minimum_mip_resolution,
-                u32::MAX,
+                settings.max_mip_levels.unwrap_or(u32::MAX),
                 settings.filter_type,
             );
             image.texture_descriptor.mip_level_count = mip_level_count;
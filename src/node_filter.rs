@@ -0,0 +1,54 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_hidden_alley_scene::{proc_scene::wildcard_match, PostProcScene};
+
+use crate::Args;
+
+/// Despawns the children of `entity` that neither match `pattern` nor have a matching
+/// descendant. Returns whether anything under `entity` matched.
+fn keep_matching(
+    commands: &mut Commands,
+    entity: Entity,
+    pattern: &str,
+    children_query: &Query<&Children>,
+    names: &Query<&Name>,
+) -> bool {
+    let mut matched = false;
+    for child in children_query.get(entity).into_iter().flatten() {
+        let keep = names
+            .get(*child)
+            .is_ok_and(|name| wildcard_match(pattern, name.as_str()))
+            || keep_matching(commands, *child, pattern, children_query, names);
+        if keep {
+            matched = true;
+        } else {
+            commands.entity(*child).despawn_recursive();
+        }
+    }
+    matched
+}
+
+/// With `--only-node`, keeps only the nodes whose name matches, with their subtrees and the
+/// nodes above them, once each scene has spawned. Runs before [`SceneSet::PostProcess`] so the
+/// rest of the scene is never processed.
+///
+/// [`SceneSet::PostProcess`]: bevy_hidden_alley_scene::SceneSet::PostProcess
+pub fn filter_scene_nodes(
+    mut commands: Commands,
+    args: Res<Args>,
+    mut filtered: Local<HashSet<Entity>>,
+    scenes: Query<Entity, With<PostProcScene>>,
+    children_query: Query<&Children>,
+    names: Query<&Name>,
+) {
+    let Some(pattern) = &args.only_node else {
+        return;
+    };
+    for scene in &scenes {
+        if !children_query.contains(scene) || !filtered.insert(scene) {
+            continue;
+        }
+        if !keep_matching(&mut commands, scene, pattern, &children_query, &names) {
+            warn!("--only-node {pattern} didn't match any node, the scene is empty");
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_hidden_alley_scene::CameraController;
+
+#[derive(Component)]
+pub struct Overlay;
+
+/// Skipped without a window, see `spawn_loading_screen`.
+pub fn spawn_overlay(mut commands: Commands, windows: Query<(), With<PrimaryWindow>>) {
+    if windows.is_empty() {
+        return;
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        Overlay,
+    ));
+}
+
+/// With `--overlay`, shows the frame rate and the camera controller's state, so a stuck
+/// modifier or mode is easy to spot when the camera feels wrong.
+pub fn update_overlay(
+    diagnostics: Res<DiagnosticsStore>,
+    keys: Res<ButtonInput<KeyCode>>,
+    controller: Query<&CameraController>,
+    mut overlay: Query<&mut Text, With<Overlay>>,
+) {
+    let Ok(mut text) = overlay.get_single_mut() else {
+        return;
+    };
+    let mut lines = Vec::new();
+    if let Some(frame_time) = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+    {
+        lines.push(format!(
+            "{:.0} fps, {:.2}ms",
+            1000.0 / frame_time,
+            frame_time
+        ));
+    }
+    if let Ok(controller) = controller.get_single() {
+        let max_speed = if keys.pressed(controller.key_run) {
+            controller.run_speed
+        } else {
+            controller.walk_speed
+        };
+        lines.push(format!(
+            "Speed: {:.1} m/s (max {:.1})",
+            controller.velocity.length(),
+            max_speed
+        ));
+        lines.push(format!(
+            "Mode: {}",
+            if controller.orbit_mode {
+                "Orbit"
+            } else {
+                "Fly"
+            }
+        ));
+        lines.push(format!(
+            "Mouse look: {}",
+            if controller.mouse_look { "on" } else { "off" }
+        ));
+        if !controller.enabled {
+            lines.push("Controller disabled".to_string());
+        }
+    }
+    text.sections[0].value = lines.join("\n");
+}
@@ -0,0 +1,322 @@
+use std::{
+    any::TypeId,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings,
+        prepass::{DeferredPrepass, DepthPrepass},
+        tonemapping::Tonemapping,
+    },
+    pbr::{ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionSettings},
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        view::{screenshot::ScreenshotManager, ColorGrading},
+    },
+    tasks::IoTaskPool,
+    utils::HashMap,
+    window::PrimaryWindow,
+};
+use bevy_hidden_alley_scene::{
+    image_readback::readback_render_target, mipmap_generator::try_into_dynamic, CameraController,
+    ImageReadback,
+};
+use image::imageops::FilterType;
+
+use crate::{Args, BenchmarkCamera};
+
+/// The top level UI nodes, hidden along with the gizmos.
+type UiRoots<'w, 's> =
+    Query<'w, 's, (Entity, &'static mut Visibility), (With<Node>, Without<Parent>)>;
+
+/// The gizmo groups and UI roots hidden by [`HiddenOverlays::hide`], with how they were before.
+#[derive(Default)]
+pub struct HiddenOverlays {
+    gizmos: HashMap<TypeId, bool>,
+    ui: HashMap<Entity, Visibility>,
+}
+
+impl HiddenOverlays {
+    fn hide(&mut self, gizmo_config: &mut GizmoConfigStore, ui_roots: &mut UiRoots) {
+        for (type_id, config, _) in gizmo_config.iter_mut() {
+            self.gizmos.insert(*type_id, config.enabled);
+            config.enabled = false;
+        }
+        for (entity, mut visibility) in ui_roots {
+            self.ui.insert(entity, *visibility);
+            *visibility = Visibility::Hidden;
+        }
+    }
+
+    fn restore(&mut self, gizmo_config: &mut GizmoConfigStore, ui_roots: &mut UiRoots) {
+        for (type_id, config, _) in gizmo_config.iter_mut() {
+            if let Some(was_enabled) = self.gizmos.remove(type_id) {
+                config.enabled = was_enabled;
+            }
+        }
+        for (entity, visibility) in self.ui.drain() {
+            if let Ok((_, mut current)) = ui_roots.get_mut(entity) {
+                *current = visibility;
+            }
+        }
+    }
+}
+
+/// While enabled, UI and gizmos are hidden and the camera controller ignores input.
+#[derive(Resource, Default)]
+pub struct PhotoMode {
+    pub enabled: bool,
+    hidden: HiddenOverlays,
+}
+
+/// `screenshot_<unix time in ms>.png`, so screenshots taken within a second don't overwrite
+/// each other.
+fn screenshot_path() -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!("screenshot_{timestamp}.png")
+}
+
+/// Press P to toggle photo mode.
+pub fn toggle_photo_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut photo_mode: ResMut<PhotoMode>,
+    mut gizmo_config: ResMut<GizmoConfigStore>,
+    mut ui_roots: UiRoots,
+    mut controllers: Query<&mut CameraController>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyP) {
+        return;
+    }
+    photo_mode.enabled = !photo_mode.enabled;
+    let enabled = photo_mode.enabled;
+
+    if enabled {
+        photo_mode.hidden.hide(&mut gizmo_config, &mut ui_roots);
+    } else {
+        photo_mode.hidden.restore(&mut gizmo_config, &mut ui_roots);
+    }
+    for mut controller in &mut controllers {
+        controller.enabled = !enabled;
+    }
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor.visible = !enabled;
+    }
+    info!(
+        "Photo mode {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+}
+
+/// Marks the temporary camera used to render a supersampled screenshot.
+#[derive(Component)]
+pub struct SupersampleCamera;
+
+pub struct PendingScreenshot {
+    camera: Entity,
+    image: Handle<Image>,
+    frames: u32,
+}
+
+/// Frames to wait before reading back the screenshot so the new camera's pipelines are ready.
+const SCREENSHOT_WAIT_FRAMES: u32 = 30;
+
+/// Press F12 to save a screenshot rendered offscreen at `--screenshot-scale` times the window
+/// resolution and downsampled back to the window size. The capture camera doesn't use TAA,
+/// the supersampling takes care of anti-aliasing instead.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn supersampled_screenshot(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut pending: Local<Option<PendingScreenshot>>,
+    mut images: ResMut<Assets<Image>>,
+    mut readback: ResMut<ImageReadback>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    main_camera: Query<
+        (
+            &Transform,
+            &Projection,
+            &ColorGrading,
+            &Tonemapping,
+            Option<&EnvironmentMapLight>,
+            Option<&FogSettings>,
+            Option<&BloomSettings>,
+            Option<&ScreenSpaceAmbientOcclusionSettings>,
+            Has<DeferredPrepass>,
+        ),
+        With<CameraController>,
+    >,
+) {
+    if let Some(screenshot) = pending.as_mut() {
+        screenshot.frames += 1;
+        if screenshot.frames == SCREENSHOT_WAIT_FRAMES {
+            readback.request(&screenshot.image);
+        }
+        if screenshot.frames > SCREENSHOT_WAIT_FRAMES {
+            if let Some(image) = readback.take(&screenshot.image) {
+                save_downsampled(image, args.screenshot_scale);
+            } else if screenshot.frames < SCREENSHOT_WAIT_FRAMES * 4 {
+                return;
+            } else {
+                warn!("Timed out waiting for the screenshot readback");
+            }
+            commands.entity(screenshot.camera).despawn_recursive();
+            images.remove(&screenshot.image);
+            *pending = None;
+        }
+        return;
+    }
+
+    if !keys.just_pressed(KeyCode::F12) {
+        return;
+    }
+    let (Ok(window), Ok(main_camera)) = (windows.get_single(), main_camera.get_single()) else {
+        return;
+    };
+    let (transform, projection, color_grading, tonemapping, env_map, fog, bloom, ssao, deferred) =
+        main_camera;
+    let scale = args.screenshot_scale.max(1);
+    let width = window.physical_width() * scale;
+    let height = window.physical_height() * scale;
+    if width.max(height) > 8192 {
+        warn!("Screenshot of {width}x{height} exceeds the max texture size, try a lower --screenshot-scale");
+        return;
+    }
+    info!("Capturing {width}x{height} screenshot");
+
+    let image = images.add(readback_render_target(width, height));
+    let mut camera = commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                hdr: true,
+                order: -1,
+                target: RenderTarget::Image(image.clone()),
+                ..default()
+            },
+            transform: *transform,
+            projection: projection.clone(),
+            color_grading: *color_grading,
+            tonemapping: *tonemapping,
+            ..default()
+        },
+        SupersampleCamera,
+    ));
+    if let Some(env_map) = env_map {
+        camera.insert(env_map.clone());
+    }
+    if let Some(fog) = fog {
+        camera.insert(fog.clone());
+    }
+    if let Some(bloom) = bloom {
+        camera.insert(bloom.clone());
+    }
+    if let Some(ssao) = ssao {
+        camera.insert(ScreenSpaceAmbientOcclusionBundle {
+            settings: ssao.clone(),
+            ..default()
+        });
+    }
+    // Deferred materials are only drawn by cameras with a deferred prepass
+    if deferred {
+        camera.insert((DepthPrepass, DeferredPrepass));
+    }
+    *pending = Some(PendingScreenshot {
+        camera: camera.id(),
+        image,
+        frames: 0,
+    });
+}
+
+/// A window screenshot waiting for the camera to hold still.
+#[derive(Default)]
+pub struct SettledScreenshot {
+    requested: bool,
+    still_frames: u32,
+    last_transform: Option<Transform>,
+    /// The overlays hidden for the frame that was just captured
+    hidden: Option<HiddenOverlays>,
+}
+
+/// Press F7 to save a screenshot of the window once the camera has been still for
+/// `--taa-settle-frames` frames, so TAA has converged and the still isn't blurry from movement.
+/// The UI and gizmos are hidden for the captured frame.
+#[allow(clippy::too_many_arguments)]
+pub fn settled_screenshot(
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut state: Local<SettledScreenshot>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut gizmo_config: ResMut<GizmoConfigStore>,
+    mut ui_roots: UiRoots,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    camera: Query<&Transform, With<BenchmarkCamera>>,
+) {
+    if let Some(mut hidden) = state.hidden.take() {
+        hidden.restore(&mut gizmo_config, &mut ui_roots);
+    }
+    let Ok(transform) = camera.get_single() else {
+        return;
+    };
+    if state.last_transform == Some(*transform) {
+        state.still_frames += 1;
+    } else {
+        state.still_frames = 0;
+        state.last_transform = Some(*transform);
+    }
+    if keys.just_pressed(KeyCode::F7) {
+        state.requested = true;
+        info!(
+            "Capturing once the camera is still for {} frames",
+            args.taa_settle_frames
+        );
+    }
+    if !state.requested || state.still_frames < args.taa_settle_frames {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let path = screenshot_path();
+    match screenshots.save_screenshot_to_disk(window, &path) {
+        Ok(_) => {
+            info!("Saving settled screenshot to {path}");
+            // The screenshot is of the frame rendered after this update, they're shown again
+            // on the next one
+            let mut hidden = HiddenOverlays::default();
+            hidden.hide(&mut gizmo_config, &mut ui_roots);
+            state.hidden = Some(hidden);
+        }
+        Err(e) => warn!("Failed to save screenshot {path}: {e}"),
+    }
+    state.requested = false;
+}
+
+fn save_downsampled(image: Image, scale: u32) {
+    IoTaskPool::get()
+        .spawn(async move {
+            let image = match try_into_dynamic(image) {
+                Ok(image) => image,
+                Err(e) => {
+                    warn!("Failed to convert screenshot: {}", e);
+                    return;
+                }
+            };
+            let image = image.resize_exact(
+                image.width() / scale.max(1),
+                image.height() / scale.max(1),
+                FilterType::Triangle,
+            );
+            let path = screenshot_path();
+            match image.save(&path) {
+                Ok(_) => info!("Saved screenshot to {path}"),
+                Err(e) => warn!("Failed to save screenshot {path}: {e}"),
+            }
+        })
+        .detach();
+}
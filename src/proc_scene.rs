@@ -0,0 +1,426 @@
+use bevy::{
+    gltf::Gltf,
+    pbr::TransmittedShadowReceiver,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{hierarchy::descendants, SceneSet};
+
+/// Post processes glTF scenes spawned with [`PostProcScene`]: masked materials get foliage
+/// transmission and are made double-sided, and the scene's own lights and cameras are removed.
+/// Runs in [`SceneSet::PostProcess`].
+pub struct ProcScenePlugin;
+
+impl Plugin for ProcScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProcSceneSettings>()
+            .add_systems(Update, proc_scene.in_set(SceneSet::PostProcess));
+    }
+}
+
+/// Marks a scene root for `proc_scene`, removed once its children have been processed.
+#[derive(Component)]
+pub struct PostProcScene;
+
+/// Configures how `proc_scene` adjusts newly spawned scenes.
+#[derive(Resource, Default)]
+pub struct ProcSceneSettings {
+    /// Names of mesh entities whose masked materials keep their culling instead of being made
+    /// double-sided, e.g. decals that z-fight with their backfaces.
+    pub single_sided: HashSet<String>,
+    /// Transmission applied to alpha blended materials, e.g. glass. Blended materials are left
+    /// alone when this is None.
+    pub glass: Option<GlassSettings>,
+    /// Boosts the emissive of glowing props like signs. Emissive is left alone when this is None.
+    pub emissive: Option<EmissiveSettings>,
+}
+
+/// Which materials get their emissive scaled by `multiplier`.
+#[derive(Clone, Debug)]
+pub struct EmissiveSettings {
+    /// Matched against the glTF material name and the entity name, `*` matches any characters,
+    /// e.g. `*_emissive`
+    pub pattern: Option<String>,
+    /// Also boost every material whose emissive isn't black
+    pub non_black: bool,
+    pub multiplier: f32,
+}
+
+impl EmissiveSettings {
+    fn matches(&self, mat: &StandardMaterial, names: &[&str]) -> bool {
+        let non_black = mat.emissive.as_linear_rgba_f32()[..3]
+            .iter()
+            .any(|c| *c > 0.0);
+        (self.non_black && non_black)
+            || self
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| names.iter().any(|name| wildcard_match(pattern, name)))
+    }
+}
+
+/// Matches `name` against `pattern`, where `*` matches any run of characters.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`, the whole name has to match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Specular transmission for alpha blended, glass-like materials.
+#[derive(Clone, Copy, Debug)]
+pub struct GlassSettings {
+    pub specular_transmission: f32,
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+impl Default for GlassSettings {
+    fn default() -> Self {
+        Self {
+            specular_transmission: 0.9,
+            thickness: 0.1,
+            ior: 1.5,
+        }
+    }
+}
+
+impl GlassSettings {
+    pub fn apply(&self, mat: &mut StandardMaterial) {
+        mat.specular_transmission = self.specular_transmission;
+        mat.thickness = self.thickness;
+        mat.ior = self.ior;
+    }
+}
+
+/// Filter for entities with any of bevy's light components.
+pub type AnyLight = Or<(With<PointLight>, With<DirectionalLight>, With<SpotLight>)>;
+
+/// Light transmission for masked foliage. Double-sided unless `double_sided` is false, then the
+/// material's culling is left alone.
+pub fn apply_foliage_transmission(mat: &mut StandardMaterial, double_sided: bool) {
+    mat.diffuse_transmission = 0.6;
+    mat.thickness = 0.2;
+    if double_sided {
+        mat.double_sided = true;
+        mat.cull_mode = None;
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn proc_scene(
+    mut commands: Commands,
+    materials_query: Query<Entity, With<PostProcScene>>,
+    children_query: Query<&Children>,
+    has_std_mat: Query<(&Handle<StandardMaterial>, Option<&Name>)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<ProcSceneSettings>,
+    // Single-sided copies of shared masked materials, keyed by the original material
+    mut single_sided: Local<HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>>>,
+    lights: Query<Entity, AnyLight>,
+    cameras: Query<Entity, With<Camera>>,
+    gltfs: Option<Res<Assets<Gltf>>>,
+    // Materials whose emissive was already boosted, since they can be shared between entities
+    mut boosted: Local<HashSet<AssetId<StandardMaterial>>>,
+) {
+    for entity in materials_query.iter() {
+        if children_query.contains(entity) {
+            // Boost emissive first so the single-sided copies below include it
+            if let Some(emissive) = &settings.emissive {
+                let material_names = gltfs
+                    .iter()
+                    .flat_map(|gltfs| gltfs.iter())
+                    .flat_map(|(_, gltf)| &gltf.named_materials)
+                    .map(|(name, handle)| (handle.id(), name.as_str()))
+                    .collect::<HashMap<_, _>>();
+                for entity in descendants(entity, &children_query) {
+                    let Ok((mat_h, name)) = has_std_mat.get(entity) else {
+                        continue;
+                    };
+                    let names = material_names
+                        .get(&mat_h.id())
+                        .copied()
+                        .into_iter()
+                        .chain(name.map(|name| name.as_str()))
+                        .collect::<Vec<_>>();
+                    let Some(mat) = materials.get_mut(mat_h) else {
+                        continue;
+                    };
+                    if !boosted.contains(&mat_h.id()) && emissive.matches(mat, &names) {
+                        mat.emissive = mat.emissive.as_rgba_linear() * emissive.multiplier;
+                        boosted.insert(mat_h.id());
+                    }
+                }
+            }
+
+            // Copy the materials of single-sided entities first, before a shared material is
+            // made double-sided in place below
+            for entity in descendants(entity, &children_query) {
+                let Ok((mat_h, Some(name))) = has_std_mat.get(entity) else {
+                    continue;
+                };
+                if !settings.single_sided.contains(name.as_str()) {
+                    continue;
+                }
+                let Some(mat) = materials.get(mat_h) else {
+                    continue;
+                };
+                if let AlphaMode::Mask(_) = mat.alpha_mode {
+                    let mut mat = mat.clone();
+                    let copy_h = single_sided.entry(mat_h.id()).or_insert_with(|| {
+                        apply_foliage_transmission(&mut mat, false);
+                        materials.add(mat)
+                    });
+                    commands
+                        .entity(entity)
+                        .insert((copy_h.clone(), TransmittedShadowReceiver));
+                }
+            }
+
+            for entity in descendants(entity, &children_query) {
+                if let Ok((mat_h, name)) = has_std_mat.get(entity) {
+                    let excluded =
+                        name.is_some_and(|name| settings.single_sided.contains(name.as_str()));
+                    if let Some(mat) = materials.get_mut(mat_h) {
+                        match (mat.alpha_mode, excluded, settings.glass) {
+                            (AlphaMode::Mask(_), false, _) => {
+                                apply_foliage_transmission(mat, true);
+                                commands.entity(entity).insert(TransmittedShadowReceiver);
+                            }
+                            (AlphaMode::Blend, _, Some(glass)) => glass.apply(mat),
+                            _ => (),
+                        }
+                    }
+                }
+
+                // Remove Default Lights
+                if lights.get(entity).is_ok() {
+                    commands.entity(entity).despawn_recursive();
+                }
+
+                // Remove Default Cameras
+                if cameras.get(entity).is_ok() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            commands.entity(entity).remove::<PostProcScene>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::Face;
+
+    use crate::{AutoInstanceMaterial, AutoInstanceMaterialPlugin, AutoInstancePlugin};
+
+    use super::*;
+
+    /// An app that only runs `proc_scene`, with `settings`. It's in `SceneSet::PostProcess` so
+    /// tests can add instancing after it.
+    fn test_app(settings: ProcSceneSettings) -> App {
+        let mut app = App::new();
+        SceneSet::configure(&mut app);
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<StandardMaterial>()
+            .insert_resource(settings)
+            .add_systems(Update, proc_scene.in_set(SceneSet::PostProcess));
+        app
+    }
+
+    #[test]
+    fn matching_emissive_materials_are_boosted() {
+        let mut app = test_app(ProcSceneSettings {
+            emissive: Some(EmissiveSettings {
+                pattern: Some("*_emissive".to_string()),
+                non_black: false,
+                multiplier: 4.0,
+            }),
+            ..default()
+        });
+
+        let emissive = Color::rgb_linear(0.5, 0.25, 0.0);
+        let mut materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        let sign_h = materials.add(StandardMaterial {
+            emissive,
+            ..default()
+        });
+        let wall_h = materials.add(StandardMaterial {
+            emissive,
+            ..default()
+        });
+        // Both entities share the sign material, it should only be boosted once
+        let sign = app
+            .world
+            .spawn((sign_h.clone(), Name::new("sign_emissive")))
+            .id();
+        let sign_back = app
+            .world
+            .spawn((sign_h.clone(), Name::new("sign_emissive")))
+            .id();
+        let wall = app.world.spawn((wall_h.clone(), Name::new("wall"))).id();
+        app.world
+            .spawn(PostProcScene)
+            .push_children(&[sign, sign_back, wall]);
+
+        app.update();
+
+        let materials = app.world.resource::<Assets<StandardMaterial>>();
+        assert_eq!(
+            materials.get(&sign_h).unwrap().emissive,
+            Color::rgb_linear(2.0, 1.0, 0.0)
+        );
+        assert_eq!(materials.get(&wall_h).unwrap().emissive, emissive);
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(wildcard_match("*_emissive", "sign_emissive"));
+        assert!(wildcard_match("sign*", "sign_emissive"));
+        assert!(wildcard_match("*neon*", "blue_neon_tube"));
+        assert!(wildcard_match("wall", "wall"));
+        assert!(!wildcard_match("wall", "wall_2"));
+        assert!(!wildcard_match("*_emissive", "emissive_sign"));
+    }
+
+    #[test]
+    fn blended_materials_get_glass_transmission() {
+        let mut app = test_app(ProcSceneSettings {
+            glass: Some(GlassSettings::default()),
+            ..default()
+        });
+
+        let mut materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        let blended_h = materials.add(StandardMaterial {
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+        let opaque_h = materials.add(StandardMaterial::default());
+        let blended = app.world.spawn(blended_h.clone()).id();
+        let opaque = app.world.spawn(opaque_h.clone()).id();
+        app.world
+            .spawn(PostProcScene)
+            .push_children(&[blended, opaque]);
+
+        app.update();
+
+        let materials = app.world.resource::<Assets<StandardMaterial>>();
+        let glass = GlassSettings::default();
+        let blended = materials.get(&blended_h).unwrap();
+        assert_eq!(blended.specular_transmission, glass.specular_transmission);
+        assert_eq!(blended.thickness, glass.thickness);
+        assert_eq!(blended.ior, glass.ior);
+        assert_eq!(blended.diffuse_transmission, 0.0);
+        let opaque = materials.get(&opaque_h).unwrap();
+        assert_eq!(opaque.specular_transmission, 0.0);
+    }
+
+    #[test]
+    fn proc_scene_runs_before_material_instancing() {
+        let mut app = test_app(ProcSceneSettings::default());
+        app.init_asset::<Mesh>().add_plugins((
+            AutoInstancePlugin,
+            AutoInstanceMaterialPlugin::<StandardMaterial>::default(),
+        ));
+
+        let masked = StandardMaterial {
+            alpha_mode: AlphaMode::Mask(0.5),
+            ..default()
+        };
+        // What proc_scene turns the masked material into
+        let tweaked = StandardMaterial {
+            diffuse_transmission: 0.6,
+            double_sided: true,
+            cull_mode: None,
+            thickness: 0.2,
+            ..masked.clone()
+        };
+        let opaque = StandardMaterial {
+            alpha_mode: AlphaMode::Opaque,
+            ..tweaked.clone()
+        };
+        let mut materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        let tweaked_h = materials.add(tweaked);
+        let opaque_h = materials.add(opaque);
+        let masked_h = materials.add(masked);
+
+        app.world.spawn((tweaked_h.clone(), AutoInstanceMaterial));
+        let opaque_entity = app
+            .world
+            .spawn((opaque_h.clone(), AutoInstanceMaterial))
+            .id();
+        let masked_entity = app
+            .world
+            .spawn((masked_h.clone(), AutoInstanceMaterial))
+            .id();
+        app.world
+            .spawn(PostProcScene)
+            .push_children(&[masked_entity]);
+
+        app.update();
+
+        // Hashed after the transmission tweaks, so it matches the pre-tweaked material
+        assert_eq!(
+            app.world.get::<Handle<StandardMaterial>>(masked_entity),
+            Some(&tweaked_h)
+        );
+        assert_eq!(
+            app.world.get::<Handle<StandardMaterial>>(opaque_entity),
+            Some(&opaque_h)
+        );
+        let materials = app.world.resource::<Assets<StandardMaterial>>();
+        assert_eq!(materials.get(&masked_h).unwrap().diffuse_transmission, 0.6);
+    }
+
+    #[test]
+    fn single_sided_materials_keep_cull_mode() {
+        let mut app = test_app(ProcSceneSettings {
+            single_sided: HashSet::from_iter(["Decal".to_string()]),
+            ..default()
+        });
+
+        let masked_h = app
+            .world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                alpha_mode: AlphaMode::Mask(0.5),
+                cull_mode: Some(Face::Back),
+                ..default()
+            });
+        let decal = app.world.spawn((masked_h.clone(), Name::new("Decal"))).id();
+        let leaves = app
+            .world
+            .spawn((masked_h.clone(), Name::new("Leaves")))
+            .id();
+        app.world
+            .spawn(PostProcScene)
+            .push_children(&[decal, leaves]);
+
+        app.update();
+
+        let materials = app.world.resource::<Assets<StandardMaterial>>();
+        let material = |entity| {
+            let handle = app.world.get::<Handle<StandardMaterial>>(entity).unwrap();
+            materials.get(handle).unwrap()
+        };
+        assert_eq!(material(decal).cull_mode, Some(Face::Back));
+        assert!(!material(decal).double_sided);
+        assert_eq!(material(decal).diffuse_transmission, 0.6);
+        assert_eq!(material(leaves).cull_mode, None);
+        assert!(material(leaves).double_sided);
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::camera::ScalingMode,
+};
+
+use crate::BenchmarkCamera;
+
+pub const MIN_FOV_DEGREES: f32 = 20.0;
+pub const MAX_FOV_DEGREES: f32 = 120.0;
+const FOV_STEP_DEGREES: f32 = 5.0;
+/// Meters visible vertically in the orthographic view at a scale of 1
+const ORTHOGRAPHIC_HEIGHT: f32 = 10.0;
+/// Scale change per scroll line in the orthographic view
+const ORTHOGRAPHIC_ZOOM: f32 = 1.1;
+
+/// Press =/- or scroll while holding Alt to change the camera's vertical FOV.
+pub fn adjust_fov(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut scroll_evr: EventReader<MouseWheel>,
+    mut cameras: Query<&mut Projection, With<BenchmarkCamera>>,
+) {
+    let mut steps = 0.0;
+    if keys.just_pressed(KeyCode::Equal) {
+        steps += 1.0;
+    }
+    if keys.just_pressed(KeyCode::Minus) {
+        steps -= 1.0;
+    }
+    let zooming = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    for ev in scroll_evr.read() {
+        if zooming && ev.unit == MouseScrollUnit::Line {
+            // Scrolling up zooms in
+            steps -= ev.y;
+        }
+    }
+    if steps == 0.0 {
+        return;
+    }
+    for mut projection in &mut cameras {
+        let Projection::Perspective(perspective) = &mut *projection else {
+            continue;
+        };
+        let fov = (perspective.fov.to_degrees() + steps * FOV_STEP_DEGREES)
+            .clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES);
+        perspective.fov = fov.to_radians();
+        info!("FOV: {fov}°");
+    }
+}
+
+/// Press O to switch the camera between its perspective projection and an orthographic one,
+/// scroll to zoom the orthographic view. Switching back restores the perspective FOV.
+pub fn toggle_orthographic(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut scroll_evr: EventReader<MouseWheel>,
+    mut perspective: Local<Option<PerspectiveProjection>>,
+    mut cameras: Query<&mut Projection, With<BenchmarkCamera>>,
+) {
+    let toggle = keys.just_pressed(KeyCode::KeyO);
+    let scroll = scroll_evr
+        .read()
+        .filter(|ev| ev.unit == MouseScrollUnit::Line)
+        .map(|ev| ev.y)
+        .sum::<f32>();
+    for mut projection in &mut cameras {
+        match &mut *projection {
+            Projection::Perspective(current) if toggle => {
+                *perspective = Some(current.clone());
+                *projection = Projection::Orthographic(OrthographicProjection {
+                    scaling_mode: ScalingMode::FixedVertical(ORTHOGRAPHIC_HEIGHT),
+                    ..default()
+                });
+                info!("Projection: orthographic");
+            }
+            Projection::Orthographic(_) if toggle => {
+                *projection = Projection::Perspective(perspective.take().unwrap_or_default());
+                info!("Projection: perspective");
+            }
+            Projection::Orthographic(orthographic) if scroll != 0.0 => {
+                orthographic.scale *= ORTHOGRAPHIC_ZOOM.powf(-scroll);
+            }
+            _ => (),
+        }
+    }
+}
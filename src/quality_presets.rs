@@ -0,0 +1,181 @@
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings,
+        experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasSettings},
+        prepass::{DepthPrepass, MotionVectorPrepass, NormalPrepass},
+    },
+    ecs::system::EntityCommands,
+    pbr::{
+        CascadeShadowConfig, CascadeShadowConfigBuilder, ScreenSpaceAmbientOcclusionBundle,
+        ScreenSpaceAmbientOcclusionSettings,
+    },
+    prelude::*,
+    render::camera::TemporalJitter,
+};
+
+use crate::{scene_bloom, Args, BenchmarkCamera, Sun};
+
+pub struct QualityPreset {
+    pub name: &'static str,
+    pub bloom: bool,
+    pub ssao: bool,
+    pub taa: bool,
+    pub shadows: bool,
+    /// `maximum_distance` of the sun's shadow cascades
+    pub shadow_distance: f32,
+}
+
+/// Selected with Numpad1-4. High matches the default scene setup.
+pub const QUALITY_PRESETS: [QualityPreset; 4] = [
+    QualityPreset {
+        name: "Low",
+        bloom: false,
+        ssao: false,
+        taa: false,
+        shadows: false,
+        shadow_distance: 20.0,
+    },
+    QualityPreset {
+        name: "Medium",
+        bloom: true,
+        ssao: false,
+        taa: true,
+        shadows: true,
+        shadow_distance: 20.0,
+    },
+    QualityPreset {
+        name: "High",
+        bloom: true,
+        ssao: true,
+        taa: true,
+        shadows: true,
+        shadow_distance: 40.0,
+    },
+    QualityPreset {
+        name: "Ultra",
+        bloom: true,
+        ssao: true,
+        taa: true,
+        shadows: true,
+        shadow_distance: 80.0,
+    },
+];
+
+const PRESET_KEYS: [KeyCode; 4] = [
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+];
+
+/// Press Numpad1-4 to switch between the Low/Medium/High/Ultra quality presets.
+pub fn switch_quality_preset(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    cameras: Query<Entity, With<BenchmarkCamera>>,
+    mut lights: Query<(&mut DirectionalLight, &mut CascadeShadowConfig), With<Sun>>,
+) {
+    let Some(preset) = PRESET_KEYS
+        .iter()
+        .position(|key| keys.just_pressed(*key))
+        .map(|i| &QUALITY_PRESETS[i])
+    else {
+        return;
+    };
+
+    for camera in &cameras {
+        let mut camera = commands.entity(camera);
+        if preset.bloom {
+            camera.insert(scene_bloom());
+        } else {
+            camera.remove::<BloomSettings>();
+        }
+        if preset.ssao {
+            camera.insert(ScreenSpaceAmbientOcclusionBundle::default());
+        } else {
+            camera.remove::<ScreenSpaceAmbientOcclusionSettings>();
+        }
+        if preset.taa {
+            camera.insert(TemporalAntiAliasBundle::default());
+        } else {
+            // The jitter has to go too, otherwise the image shakes without TAA resolving it
+            camera.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+        }
+        remove_unused_prepasses(&mut camera, &args, preset.taa, preset.ssao);
+    }
+    for (mut light, mut cascades) in &mut lights {
+        light.shadows_enabled = preset.shadows;
+        *cascades = CascadeShadowConfigBuilder {
+            num_cascades: 3,
+            maximum_distance: preset.shadow_distance,
+            ..default()
+        }
+        .into();
+    }
+    info!("Quality preset: {}", preset.name);
+}
+
+/// Removes the prepasses the TAA and SSAO bundles added once neither uses them, so turning
+/// them off saves the prepass cost too. `--deferred` needs depth and normals, and depth of field
+/// needs depth.
+fn remove_unused_prepasses(camera: &mut EntityCommands, args: &Args, taa: bool, ssao: bool) {
+    if !taa {
+        camera.remove::<MotionVectorPrepass>();
+    }
+    if !ssao && !args.deferred {
+        camera.remove::<NormalPrepass>();
+    }
+    if !taa && !ssao && !args.deferred && !args.dof {
+        camera.remove::<DepthPrepass>();
+    }
+}
+
+const MSAA_MODES: [Msaa; 4] = [Msaa::Off, Msaa::Sample2, Msaa::Sample4, Msaa::Sample8];
+
+/// Press N to cycle MSAA. TAA can't be used with MSAA, so it's removed from the camera while
+/// MSAA is on and added back when returning to `Msaa::Off`.
+#[allow(clippy::type_complexity)]
+pub fn cycle_msaa(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut msaa: ResMut<Msaa>,
+    mut removed_taa: Local<bool>,
+    cameras: Query<
+        (
+            Entity,
+            Has<TemporalAntiAliasSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+        ),
+        With<BenchmarkCamera>,
+    >,
+) {
+    if !keys.just_pressed(KeyCode::KeyN) {
+        return;
+    }
+    if args.deferred {
+        info!("MSAA isn't supported with --deferred");
+        return;
+    }
+    let next = MSAA_MODES
+        .iter()
+        .position(|mode| *mode == *msaa)
+        .map_or(0, |i| (i + 1) % MSAA_MODES.len());
+    *msaa = MSAA_MODES[next];
+    info!("MSAA: {:?}", *msaa);
+    for (camera, has_taa, has_ssao) in &cameras {
+        let mut camera = commands.entity(camera);
+        if *msaa != Msaa::Off && has_taa {
+            camera.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+            remove_unused_prepasses(&mut camera, &args, false, has_ssao);
+            *removed_taa = true;
+        } else if *msaa == Msaa::Off && *removed_taa {
+            camera.insert(TemporalAntiAliasBundle::default());
+            *removed_taa = false;
+        }
+        if *msaa != Msaa::Off && has_ssao {
+            info!("SSAO is skipped while MSAA is on");
+        }
+    }
+}
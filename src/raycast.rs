@@ -0,0 +1,31 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+
+/// Returns the closest entity whose bounding box the ray enters, and the distance to it.
+/// Boxes that contain the ray origin are skipped, otherwise the large boxes of the ground and
+/// walls would always be hit first. This is only as precise as the boxes, there is no triangle test.
+pub fn raycast_aabbs<'a>(
+    ray: Ray3d,
+    entities: impl IntoIterator<Item = (Entity, &'a Aabb, &'a GlobalTransform)>,
+) -> Option<(Entity, f32)> {
+    entities
+        .into_iter()
+        .filter_map(|(entity, aabb, transform)| {
+            // Test in the entity's local space so rotated boxes stay tight
+            let world_to_local = transform.affine().inverse();
+            let origin = world_to_local.transform_point3(ray.origin);
+            let direction = world_to_local.transform_vector3(*ray.direction);
+            ray_aabb_entry(origin, direction, aabb).map(|distance| (entity, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Distance along the ray to where it enters the box. The direction isn't normalized after the
+/// transform to local space, so the distance stays in world units.
+fn ray_aabb_entry(origin: Vec3, direction: Vec3, aabb: &Aabb) -> Option<f32> {
+    let inv_direction = direction.recip();
+    let t1 = (Vec3::from(aabb.min()) - origin) * inv_direction;
+    let t2 = (Vec3::from(aabb.max()) - origin) * inv_direction;
+    let near = t1.min(t2).max_element();
+    let far = t1.max(t2).min_element();
+    (near > 0.0 && near <= far).then_some(near)
+}
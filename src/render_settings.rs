@@ -0,0 +1,108 @@
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings, experimental::taa::TemporalAntiAliasSettings,
+        tonemapping::Tonemapping,
+    },
+    pbr::{CascadeShadowConfig, DirectionalLightShadowMap, ScreenSpaceAmbientOcclusionSettings},
+    prelude::*,
+    render::view::ColorGrading,
+};
+
+use crate::{
+    environment::EnvironmentMaps, light_controls::SunTemperature, Args, BenchmarkCamera, SkyColor,
+    Sun,
+};
+
+fn hex(color: Color) -> String {
+    let [r, g, b, _] = color.as_rgba_u8();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Press F8 to print the current render settings, followed by the command line flags that
+/// reproduce the ones that have a flag.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn print_render_settings(
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    camera: Query<
+        (
+            &ColorGrading,
+            Option<&Tonemapping>,
+            Has<BloomSettings>,
+            Has<ScreenSpaceAmbientOcclusionSettings>,
+            Has<TemporalAntiAliasSettings>,
+            &Projection,
+        ),
+        With<BenchmarkCamera>,
+    >,
+    sun: Query<(&DirectionalLight, &CascadeShadowConfig), With<Sun>>,
+    shadow_map: Res<DirectionalLightShadowMap>,
+    ambient: Res<AmbientLight>,
+    environment: Res<EnvironmentMaps>,
+    sun_temperature: Res<SunTemperature>,
+    sky_color: Res<SkyColor>,
+) {
+    if !keys.just_pressed(KeyCode::F8) {
+        return;
+    }
+    let Ok((color_grading, tonemapping, bloom, ssao, taa, projection)) = camera.get_single() else {
+        return;
+    };
+    #[cfg(not(feature = "bevy_main"))]
+    let exposure = color_grading.exposure;
+    #[cfg(feature = "bevy_main")]
+    let exposure = color_grading.global.exposure;
+
+    println!("Render settings:");
+    println!("  Exposure: {exposure}");
+    println!(
+        "  Tonemapping: {:?}",
+        tonemapping.copied().unwrap_or_default()
+    );
+    println!("  Bloom: {bloom}, SSAO: {ssao}, TAA: {taa}");
+    for (light, cascades) in &sun {
+        println!(
+            "  Sun shadows: {}, cascades: {}, max distance: {}",
+            light.shadows_enabled,
+            cascades.bounds.len(),
+            cascades.bounds.last().copied().unwrap_or_default()
+        );
+    }
+    println!("  Shadow map size: {}", shadow_map.size);
+    println!("  Environment map blend: {:.1}", environment.blend);
+
+    let mut flags = vec![
+        format!("--scene {}", args.scene),
+        format!("--env-diffuse {}", args.env_diffuse),
+        format!("--env-specular {}", args.env_specular),
+        format!("--env-intensity {}", environment.a.intensity),
+    ];
+    if let Some(diffuse) = &args.env_diffuse_b {
+        flags.push(format!("--env-diffuse-b {diffuse}"));
+    }
+    if let Some(specular) = &args.env_specular_b {
+        flags.push(format!("--env-specular-b {specular}"));
+    }
+    if environment.b.intensity != environment.a.intensity {
+        flags.push(format!("--env-intensity-b {}", environment.b.intensity));
+    }
+    flags.push(format!("--ambient-brightness {}", ambient.brightness));
+    flags.push(format!("--ambient-color {}", hex(ambient.color)));
+    flags.push(format!("--sun-temp {}", sun_temperature.0));
+    flags.push(format!("--shadow-map-size {}", shadow_map.size));
+    if let Projection::Perspective(perspective) = projection {
+        flags.push(format!("--fov {:.1}", perspective.fov.to_degrees()));
+    }
+    // The sky can change at runtime, e.g. with the time of day
+    flags.push(format!("--sky-color {}", hex(sky_color.flag_color())));
+    if args.minimal {
+        flags.push("--minimal".to_string());
+    }
+    if args.deferred {
+        flags.push("--deferred".to_string());
+    }
+    if args.instance {
+        flags.push("--instance".to_string());
+    }
+    println!("  Flags: {}", flags.join(" "));
+}
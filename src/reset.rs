@@ -0,0 +1,140 @@
+use bevy::{pbr::CascadeShadowConfig, prelude::*, render::view::ColorGrading};
+
+use crate::{
+    environment::EnvironmentMaps, light_controls::SunTemperature, lighting_profile::TimeOfDay,
+    BenchmarkCamera, GrifLight,
+};
+
+struct InitialLight {
+    transform: Transform,
+    visibility: Visibility,
+    directional: Option<(DirectionalLight, CascadeShadowConfig)>,
+    point: Option<PointLight>,
+    spot: Option<SpotLight>,
+}
+
+/// The tweakable values as they were right after `setup`, restored with Backspace.
+#[derive(Resource)]
+pub struct InitialSceneState {
+    color_grading: ColorGrading,
+    fog: Option<FogSettings>,
+    lights: Vec<(Entity, InitialLight)>,
+    ambient: AmbientLight,
+    environment_intensities: (f32, f32),
+    environment_blend: f32,
+    sun_temperature: f32,
+}
+
+// ColorGrading is Copy in bevy 0.13 but not on main
+#[allow(clippy::type_complexity, clippy::clone_on_copy)]
+pub fn capture_initial_state(
+    mut commands: Commands,
+    camera: Query<(&ColorGrading, Option<&FogSettings>), With<BenchmarkCamera>>,
+    lights: Query<
+        (
+            Entity,
+            &Transform,
+            &Visibility,
+            Option<(&DirectionalLight, &CascadeShadowConfig)>,
+            Option<&PointLight>,
+            Option<&SpotLight>,
+        ),
+        With<GrifLight>,
+    >,
+    ambient: Res<AmbientLight>,
+    environment: Res<EnvironmentMaps>,
+    sun_temperature: Res<SunTemperature>,
+) {
+    let Ok((color_grading, fog)) = camera.get_single() else {
+        return;
+    };
+    let lights = lights
+        .iter()
+        .map(
+            |(entity, transform, visibility, directional, point, spot)| {
+                let light = InitialLight {
+                    transform: *transform,
+                    visibility: *visibility,
+                    directional: directional
+                        .map(|(light, cascades)| (light.clone(), cascades.clone())),
+                    point: point.cloned(),
+                    spot: spot.cloned(),
+                };
+                (entity, light)
+            },
+        )
+        .collect();
+    commands.insert_resource(InitialSceneState {
+        color_grading: color_grading.clone(),
+        fog: fog.cloned(),
+        lights,
+        ambient: ambient.clone(),
+        environment_intensities: (environment.a.intensity, environment.b.intensity),
+        environment_blend: environment.blend,
+        sun_temperature: sun_temperature.0,
+    });
+}
+
+/// Press Backspace to undo all runtime tweaks to the camera, lights and environment. The sun,
+/// ambient light and sky go back to the lighting profile at the current time of day.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn reset_tweaks(
+    keys: Res<ButtonInput<KeyCode>>,
+    initial: Option<Res<InitialSceneState>>,
+    mut camera: Query<(&mut ColorGrading, Option<&mut FogSettings>), With<BenchmarkCamera>>,
+    mut lights: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            Option<(&mut DirectionalLight, &mut CascadeShadowConfig)>,
+            Option<&mut PointLight>,
+            Option<&mut SpotLight>,
+        ),
+        With<GrifLight>,
+    >,
+    mut ambient: ResMut<AmbientLight>,
+    mut environment: ResMut<EnvironmentMaps>,
+    mut sun_temperature: ResMut<SunTemperature>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if !keys.just_pressed(KeyCode::Backspace) {
+        return;
+    }
+    let Some(initial) = initial else {
+        return;
+    };
+    if let Ok((mut color_grading, fog)) = camera.get_single_mut() {
+        color_grading.clone_from(&initial.color_grading);
+        if let (Some(mut fog), Some(initial_fog)) = (fog, &initial.fog) {
+            *fog = initial_fog.clone();
+        }
+    }
+    for (entity, light) in &initial.lights {
+        let Ok((mut transform, mut visibility, directional, point, spot)) = lights.get_mut(*entity)
+        else {
+            continue;
+        };
+        *transform = light.transform;
+        *visibility = light.visibility;
+        if let (Some((mut current, mut cascades)), Some((initial, initial_cascades))) =
+            (directional, &light.directional)
+        {
+            *current = initial.clone();
+            *cascades = initial_cascades.clone();
+        }
+        if let (Some(mut current), Some(initial)) = (point, light.point) {
+            *current = initial;
+        }
+        if let (Some(mut current), Some(initial)) = (spot, light.spot) {
+            *current = initial;
+        }
+    }
+    *ambient = initial.ambient.clone();
+    (environment.a.intensity, environment.b.intensity) = initial.environment_intensities;
+    environment.blend = initial.environment_blend;
+    sun_temperature.0 = initial.sun_temperature;
+    // The initial state is the lighting from setup, apply_lighting_profile puts the profile's
+    // lighting for the current time back on top of it
+    time_of_day.set_changed();
+    info!("Reset all tweaks");
+}
@@ -0,0 +1,182 @@
+use bevy::{prelude::*, render::mesh::PrimitiveTopology};
+use bevy_hidden_alley_scene::{proc_scene::AnyLight, PostProcScene};
+
+use crate::{Args, GrifLight};
+
+pub fn triangle_count(mesh: &Mesh) -> usize {
+    let count = mesh
+        .indices()
+        .map(|indices| indices.len())
+        .unwrap_or_else(|| mesh.count_vertices());
+    match mesh.primitive_topology() {
+        PrimitiveTopology::TriangleList => count / 3,
+        PrimitiveTopology::TriangleStrip => count.saturating_sub(2),
+        _ => 0,
+    }
+}
+
+/// Prints the total triangle and draw call count once the scene and all its meshes are loaded.
+/// Draw calls are counted as entities with a mesh, before any batching or instancing.
+pub fn log_scene_stats(
+    mut done: Local<bool>,
+    meshes: Res<Assets<Mesh>>,
+    mesh_entities: Query<&Handle<Mesh>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+) {
+    if *done || !loading_scenes.is_empty() || mesh_entities.is_empty() {
+        return;
+    }
+    let mut triangles = 0;
+    for mesh_h in &mesh_entities {
+        let Some(mesh) = meshes.get(mesh_h) else {
+            return;
+        };
+        triangles += triangle_count(mesh);
+    }
+    *done = true;
+    println!(
+        "Scene stats: {} triangles, {} draw calls",
+        triangles,
+        mesh_entities.iter().len()
+    );
+}
+
+type HierarchyNode<'a> = (
+    Option<&'a Name>,
+    Option<&'a Handle<Mesh>>,
+    Option<&'a Handle<StandardMaterial>>,
+);
+
+/// With `--dump-hierarchy`, prints the entity tree of each scene once it has spawned and been
+/// processed by `proc_scene`.
+pub fn dump_hierarchy(
+    mut done: Local<bool>,
+    roots: Query<Entity, With<Handle<Scene>>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    children: Query<&Children>,
+    nodes: Query<HierarchyNode>,
+    materials: Res<Assets<StandardMaterial>>,
+) {
+    if *done || !loading_scenes.is_empty() || roots.is_empty() {
+        return;
+    }
+    *done = true;
+    for root in &roots {
+        print_node(root, 0, &children, &nodes, &materials);
+    }
+}
+
+fn print_node(
+    entity: Entity,
+    depth: usize,
+    children: &Query<&Children>,
+    nodes: &Query<HierarchyNode>,
+    materials: &Assets<StandardMaterial>,
+) {
+    let Ok((name, mesh, material)) = nodes.get(entity) else {
+        return;
+    };
+    let mut line = format!("{}{:?}", "  ".repeat(depth), entity);
+    if let Some(name) = name {
+        line += &format!(" \"{}\"", name);
+    }
+    if mesh.is_some() {
+        line += " mesh";
+    }
+    if let Some(material) = material {
+        line += " material";
+        if let Some(material) = materials.get(material) {
+            line += &format!(" {:?}", material.alpha_mode);
+        }
+    }
+    println!("{line}");
+    for child in children.get(entity).into_iter().flatten() {
+        print_node(*child, depth + 1, children, nodes, materials);
+    }
+}
+
+type CameraInfo<'a> = (
+    Entity,
+    Option<&'a Name>,
+    &'a Camera,
+    &'a Transform,
+    Option<&'a Projection>,
+);
+
+type LightInfo<'a> = (
+    Entity,
+    Option<&'a Name>,
+    Option<&'a DirectionalLight>,
+    Option<&'a PointLight>,
+    Option<&'a SpotLight>,
+    Has<GrifLight>,
+);
+
+fn label(entity: Entity, name: Option<&Name>) -> String {
+    match name {
+        Some(name) => format!("{entity:?} \"{name}\""),
+        None => format!("{entity:?}"),
+    }
+}
+
+/// With `--list-cameras`/`--list-lights`, prints every camera and light once the scene has
+/// loaded, after `proc_scene` has removed the scene's own.
+pub fn list_cameras_and_lights(
+    mut done: Local<bool>,
+    args: Res<Args>,
+    roots: Query<(), With<Handle<Scene>>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    cameras: Query<CameraInfo>,
+    lights: Query<LightInfo, AnyLight>,
+) {
+    if *done || !loading_scenes.is_empty() || roots.is_empty() {
+        return;
+    }
+    *done = true;
+    if args.list_cameras {
+        println!("Cameras:");
+        for (entity, name, camera, transform, projection) in &cameras {
+            let projection = match projection {
+                Some(Projection::Perspective(p)) => format!("fov {:.1}°", p.fov.to_degrees()),
+                Some(Projection::Orthographic(o)) => format!("orthographic, scale {}", o.scale),
+                None => "no projection".to_string(),
+            };
+            println!(
+                "  {}: translation {}, rotation {}, {}, active: {}",
+                label(entity, name),
+                transform.translation,
+                transform.rotation,
+                projection,
+                camera.is_active
+            );
+        }
+    }
+    if args.list_lights {
+        println!("Lights:");
+        for (entity, name, directional, point, spot, grif) in &lights {
+            let (kind, color, intensity, shadows) = if let Some(light) = directional {
+                let illuminance = format!("{} lux", light.illuminance);
+                (
+                    "directional",
+                    light.color,
+                    illuminance,
+                    light.shadows_enabled,
+                )
+            } else if let Some(light) = point {
+                let intensity = format!("{} lm", light.intensity);
+                ("point", light.color, intensity, light.shadows_enabled)
+            } else if let Some(light) = spot {
+                let intensity = format!("{} lm", light.intensity);
+                ("spot", light.color, intensity, light.shadows_enabled)
+            } else {
+                continue;
+            };
+            let [r, g, b, _] = color.as_rgba_u8();
+            println!(
+                "  {}: {kind}, color #{r:02x}{g:02x}{b:02x}, {intensity}, shadows: {shadows}, \
+                GrifLight: {grif}",
+                label(entity, name),
+            );
+        }
+    }
+}
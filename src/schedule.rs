@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use crate::{mipmap_generator::MipmapTasks, PostProcScene};
+
+/// Groups the scene's `Update` systems so others can be ordered against them. The sets run in
+/// declaration order once chained with [`SceneSet::configure`].
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SceneSet {
+    /// Tweaks materials, lights and cameras of newly spawned scenes.
+    PostProcess,
+    /// Merges duplicate meshes and materials. Runs after `PostProcess` so materials are hashed
+    /// with their final properties.
+    Instancing,
+    /// Starts and collects mipmap generation for materials.
+    Mipmaps,
+    /// Moves the camera through the benchmark and records frame times.
+    Benchmark,
+}
+
+impl SceneSet {
+    pub fn configure(app: &mut App) {
+        app.configure_sets(
+            Update,
+            (
+                SceneSet::PostProcess,
+                SceneSet::Instancing,
+                SceneSet::Mipmaps,
+                SceneSet::Benchmark,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Frames to wait after the scene is ready so pipelines and shadow maps settle before measuring
+/// or capturing.
+pub const SCENE_SETTLE_FRAMES: u32 = 60;
+
+/// Run condition for systems that need the scene spawned and its mipmaps generated.
+pub fn scene_ready(
+    mip_tasks: Option<Res<MipmapTasks<StandardMaterial>>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+) -> bool {
+    loading_scenes.is_empty() && mip_tasks.is_some_and(|tasks| tasks.is_empty())
+}
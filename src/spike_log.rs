@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+use bevy_hidden_alley_scene::{
+    mipmap_generator::MipmapTasks, AutoInstanceMaterial, AutoInstanceMesh, PostProcScene,
+};
+
+use crate::{Args, BenchmarkCamera};
+
+/// How much each frame moves the running average, about the last 60 frames.
+const AVERAGE_WEIGHT: f32 = 1.0 / 60.0;
+/// Frames to build up the running average before logging spikes.
+const WARMUP_FRAMES: u32 = 60;
+
+#[derive(Default)]
+pub struct SpikeState {
+    average: f32,
+    frames: u32,
+}
+
+/// With `--log-spikes`, logs frames that take longer than `--spike-threshold` times the running
+/// average, with the camera transform and any scene loading, instancing or mipmap work that was
+/// pending that frame.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn log_frame_spikes(
+    mut state: Local<SpikeState>,
+    args: Res<Args>,
+    time: Res<Time<Real>>,
+    camera: Query<&Transform, With<BenchmarkCamera>>,
+    loading_scenes: Query<(), With<PostProcScene>>,
+    instancing: Query<(), Or<(With<AutoInstanceMesh>, With<AutoInstanceMaterial>)>>,
+    mip_tasks: Option<Res<MipmapTasks<StandardMaterial>>>,
+) {
+    let delta = time.delta_seconds();
+    if delta == 0.0 {
+        return;
+    }
+    state.frames += 1;
+    if state.frames == 1 {
+        state.average = delta;
+        return;
+    }
+    if state.frames > WARMUP_FRAMES && delta > state.average * args.spike_threshold {
+        let mut line = format!(
+            "Frame spike at {:.2}s: {:.2}ms, {:.1}x the {:.2}ms average",
+            time.elapsed_seconds(),
+            delta * 1000.0,
+            delta / state.average,
+            state.average * 1000.0
+        );
+        if let Ok(transform) = camera.get_single() {
+            line += &format!(", camera {} {}", transform.translation, transform.rotation);
+        }
+        let pending = [
+            ("loading scenes", loading_scenes.iter().len()),
+            ("instancing", instancing.iter().len()),
+            ("mipmap tasks", mip_tasks.map_or(0, |tasks| tasks.len())),
+        ];
+        for (work, count) in pending {
+            if count > 0 {
+                line += &format!(", {count} {work}");
+            }
+        }
+        warn!("{line}");
+    }
+    state.average += (delta - state.average) * AVERAGE_WEIGHT;
+}
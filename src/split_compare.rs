@@ -0,0 +1,328 @@
+use bevy::{
+    core_pipeline::{
+        bloom::BloomSettings,
+        experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasSettings},
+        prepass::{DeferredPrepass, DepthPrepass},
+        tonemapping::Tonemapping,
+    },
+    ecs::system::EntityCommands,
+    pbr::{ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionSettings},
+    prelude::*,
+    render::{
+        camera::{ClearColorConfig, TemporalJitter, Viewport},
+        view::ColorGrading,
+    },
+    window::PrimaryWindow,
+};
+
+use bevy_hidden_alley_scene::SCENE_SETTLE_FRAMES;
+
+use crate::{
+    scene_bloom, Args, BenchmarkCamera, BenchmarkFinished, BenchmarkRunning, SkyColor,
+    StartBenchmark,
+};
+
+/// Per camera settings that can differ between the two halves of `--split-compare`. Only
+/// per camera post processing can be compared, settings like instancing or the opaque render
+/// path apply to the whole app.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitConfig {
+    /// Bloom, fog, TAA and SSAO
+    Full,
+    /// None of the post processing, like `--minimal`
+    Minimal,
+    NoBloom,
+    NoFog,
+    NoTaa,
+    NoSsao,
+}
+
+impl SplitConfig {
+    fn name(self) -> &'static str {
+        match self {
+            SplitConfig::Full => "full",
+            SplitConfig::Minimal => "minimal",
+            SplitConfig::NoBloom => "no-bloom",
+            SplitConfig::NoFog => "no-fog",
+            SplitConfig::NoTaa => "no-taa",
+            SplitConfig::NoSsao => "no-ssao",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, String> {
+        Ok(match value {
+            "full" => SplitConfig::Full,
+            "minimal" => SplitConfig::Minimal,
+            "no-bloom" => SplitConfig::NoBloom,
+            "no-fog" => SplitConfig::NoFog,
+            "no-taa" => SplitConfig::NoTaa,
+            "no-ssao" => SplitConfig::NoSsao,
+            _ => {
+                return Err(format!(
+                    "Unknown split config {value}, expected full, minimal, no-bloom, no-fog, \
+                    no-taa or no-ssao"
+                ))
+            }
+        })
+    }
+
+    /// Inserts or removes bloom, fog, TAA and SSAO on the camera to match this config. Only the
+    /// settings are removed, the prepasses from the bundles stay since `--deferred` needs them.
+    fn apply(self, cam: &mut EntityCommands, sky_color: Color) {
+        let enabled = |config| self != SplitConfig::Minimal && self != config;
+        if enabled(SplitConfig::NoBloom) {
+            cam.insert(scene_bloom());
+        } else {
+            cam.remove::<BloomSettings>();
+        }
+        if enabled(SplitConfig::NoFog) {
+            cam.insert(FogSettings {
+                color: sky_color,
+                falloff: FogFalloff::Linear {
+                    start: 4.0,
+                    end: 500.0,
+                },
+                ..default()
+            });
+        } else {
+            cam.remove::<FogSettings>();
+        }
+        if enabled(SplitConfig::NoTaa) {
+            cam.insert(TemporalAntiAliasBundle::default());
+        } else {
+            cam.remove::<(TemporalAntiAliasSettings, TemporalJitter)>();
+        }
+        if enabled(SplitConfig::NoSsao) {
+            cam.insert(ScreenSpaceAmbientOcclusionBundle::default());
+        } else {
+            cam.remove::<ScreenSpaceAmbientOcclusionSettings>();
+        }
+    }
+}
+
+/// The configs for the left and right half of the window, from `--split-compare <a>:<b>`.
+#[derive(Clone, Copy, Debug)]
+pub struct SplitCompare {
+    pub left: SplitConfig,
+    pub right: SplitConfig,
+}
+
+pub fn parse_split_compare(value: &str) -> Result<SplitCompare, String> {
+    let (left, right) = value
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid split compare {value}, expected <a>:<b>"))?;
+    Ok(SplitCompare {
+        left: SplitConfig::parse(left)?,
+        right: SplitConfig::parse(right)?,
+    })
+}
+
+/// The camera drawing the right half with `--split-compare`. It follows the `BenchmarkCamera`,
+/// which draws the left half.
+#[derive(Component)]
+pub struct SplitCompareCamera;
+
+type MainCameraComponents<'a> = (
+    Entity,
+    &'a Camera,
+    &'a Transform,
+    &'a Projection,
+    &'a ColorGrading,
+    &'a Tonemapping,
+    Option<&'a EnvironmentMapLight>,
+    Has<DeferredPrepass>,
+);
+
+/// Spawns a second camera for the right half with the main camera's settings, then applies the
+/// `--split-compare` configs to both.
+pub fn setup_split_compare(
+    mut commands: Commands,
+    args: Res<Args>,
+    sky_color: Res<SkyColor>,
+    main_camera: Query<MainCameraComponents, With<BenchmarkCamera>>,
+) {
+    let Some(split) = args.split_compare else {
+        return;
+    };
+    let Ok((entity, camera, transform, projection, color_grading, tonemapping, env_map, deferred)) =
+        main_camera.get_single()
+    else {
+        return;
+    };
+    info!(
+        "Split compare: {} on the left, {} on the right",
+        split.left.name(),
+        split.right.name()
+    );
+    split.left.apply(&mut commands.entity(entity), sky_color.0);
+
+    let mut right = commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                order: camera.order + 1,
+                // The left half is already cleared by the main camera
+                clear_color: ClearColorConfig::None,
+                ..camera.clone()
+            },
+            transform: *transform,
+            projection: projection.clone(),
+            color_grading: *color_grading,
+            tonemapping: *tonemapping,
+            ..default()
+        },
+        SplitCompareCamera,
+        Name::new("Split Compare Camera"),
+    ));
+    if let Some(env_map) = env_map {
+        right.insert(env_map.clone());
+    }
+    if deferred {
+        right.insert((DepthPrepass, DeferredPrepass));
+    }
+    split.right.apply(&mut right, sky_color.0);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SplitSide {
+    Left,
+    Right,
+}
+
+/// Progress of measuring each `--split-compare` config on its own. While a side is measured it
+/// is drawn fullscreen and the other camera is turned off.
+#[derive(Resource, Default)]
+pub struct SplitCompareBench {
+    measuring: Option<SplitSide>,
+    settled_frames: u32,
+    running: bool,
+    left_frame_time_ms: f32,
+}
+
+/// Press F9 with `--split-compare` to benchmark the left config fullscreen, then the right
+/// one, and print both frame times. The split view comes back afterwards.
+#[allow(clippy::too_many_arguments)]
+pub fn bench_split_compare(
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<Args>,
+    mut bench: ResMut<SplitCompareBench>,
+    running: Res<BenchmarkRunning>,
+    mut start: EventWriter<StartBenchmark>,
+    mut finished: EventReader<BenchmarkFinished>,
+) {
+    let Some(split) = args.split_compare else {
+        return;
+    };
+    let Some(side) = bench.measuring else {
+        if keys.just_pressed(KeyCode::F9) && !running.0 {
+            info!("Measuring {} fullscreen", split.left.name());
+            bench.measuring = Some(SplitSide::Left);
+            bench.settled_frames = 0;
+        }
+        return;
+    };
+    if bench.running {
+        let Some(result) = finished.read().last() else {
+            return;
+        };
+        bench.running = false;
+        bench.settled_frames = 0;
+        if side == SplitSide::Left {
+            bench.left_frame_time_ms = result.avg_frame_time_ms;
+            bench.measuring = Some(SplitSide::Right);
+            info!("Measuring {} fullscreen", split.right.name());
+            return;
+        }
+        println!(
+            "Split compare frame times: {} {:.2}ms, {} {:.2}ms",
+            split.left.name(),
+            bench.left_frame_time_ms,
+            split.right.name(),
+            result.avg_frame_time_ms
+        );
+        bench.measuring = None;
+        return;
+    }
+    // Settles after switching cameras so the new pipelines are ready
+    bench.settled_frames += 1;
+    if bench.settled_frames >= SCENE_SETTLE_FRAMES {
+        start.send(StartBenchmark);
+        bench.running = true;
+    }
+}
+
+/// Keeps the split compare camera on the main camera's view and both viewports on their half
+/// of the window, or shows one of them fullscreen while `bench_split_compare` measures it.
+#[allow(clippy::type_complexity)]
+pub fn update_split_compare(
+    bench: Res<SplitCompareBench>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut main_camera: Query<
+        (&mut Camera, &Transform, &Projection),
+        (With<BenchmarkCamera>, Without<SplitCompareCamera>),
+    >,
+    mut split_camera: Query<
+        (&mut Camera, &mut Transform, &mut Projection),
+        (With<SplitCompareCamera>, Without<BenchmarkCamera>),
+    >,
+) {
+    let (Ok(window), Ok((mut main, main_transform, main_projection))) =
+        (window.get_single(), main_camera.get_single_mut())
+    else {
+        return;
+    };
+    let Ok((mut split, mut transform, mut projection)) = split_camera.get_single_mut() else {
+        return;
+    };
+    *transform = *main_transform;
+    *projection = main_projection.clone();
+
+    match bench.measuring {
+        Some(SplitSide::Left) => {
+            set_view(&mut main, true, None);
+            set_view(&mut split, false, None);
+        }
+        Some(SplitSide::Right) => {
+            set_view(&mut main, false, None);
+            set_view(&mut split, true, None);
+        }
+        None => {
+            let size = UVec2::new(window.physical_width(), window.physical_height());
+            let half = UVec2::new((size.x / 2).max(1), size.y.max(1));
+            set_view(&mut main, true, Some((UVec2::ZERO, half)));
+            set_view(
+                &mut split,
+                true,
+                Some((UVec2::new(size.x.saturating_sub(half.x), 0), half)),
+            );
+        }
+    }
+    // The right half relies on the main camera clearing the window, unless it's drawn alone
+    let clear = bench.measuring == Some(SplitSide::Right);
+    if clear == matches!(split.clear_color, ClearColorConfig::None) {
+        split.clear_color = if clear {
+            ClearColorConfig::Default
+        } else {
+            ClearColorConfig::None
+        };
+    }
+}
+
+/// Only touches the camera when something changed, so it isn't flagged as changed every frame.
+/// `viewport` is the position and size, None is fullscreen.
+fn set_view(camera: &mut Mut<Camera>, active: bool, viewport: Option<(UVec2, UVec2)>) {
+    if camera.is_active != active {
+        camera.is_active = active;
+    }
+    let current = camera
+        .viewport
+        .as_ref()
+        .map(|viewport| (viewport.physical_position, viewport.physical_size));
+    if current == viewport {
+        return;
+    }
+    camera.viewport = viewport.map(|(position, size)| Viewport {
+        physical_position: position,
+        physical_size: size,
+        ..default()
+    });
+}
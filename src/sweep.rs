@@ -0,0 +1,216 @@
+use bevy::{
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder, MAX_CASCADES_PER_LIGHT},
+    prelude::*,
+};
+use bevy_hidden_alley_scene::SCENE_SETTLE_FRAMES;
+
+use crate::{Args, BenchmarkFinished, StartBenchmark, Sun};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SweepSetting {
+    /// `maximum_distance` of the sun's shadow cascades
+    ShadowDistance,
+    /// Number of sun shadow cascades
+    Cascades,
+    /// MSAA sample count, 1 is off
+    Msaa,
+}
+
+impl SweepSetting {
+    fn name(self) -> &'static str {
+        match self {
+            SweepSetting::ShadowDistance => "shadow-distance",
+            SweepSetting::Cascades => "cascades",
+            SweepSetting::Msaa => "msaa",
+        }
+    }
+}
+
+/// A setting and the values to run the benchmark with, from `--sweep <setting>:<v1>,<v2>,..`.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    pub setting: SweepSetting,
+    pub values: Vec<f32>,
+}
+
+pub fn parse_sweep(value: &str) -> Result<Sweep, String> {
+    let (setting, values) = value
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid sweep {value}, expected <setting>:<v1>,<v2>,.."))?;
+    let setting = match setting {
+        "shadow-distance" => SweepSetting::ShadowDistance,
+        "cascades" => SweepSetting::Cascades,
+        "msaa" => SweepSetting::Msaa,
+        _ => {
+            return Err(format!(
+                "Unknown sweep setting {setting}, expected shadow-distance, cascades or msaa"
+            ))
+        }
+    };
+    let values = values
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("Invalid sweep value {v}: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // Values CascadeShadowConfigBuilder would panic on, or silently truncate
+    let first_cascade = CascadeShadowConfigBuilder::default().first_cascade_far_bound;
+    let invalid = |v: f32| match setting {
+        SweepSetting::Msaa => (![1.0, 2.0, 4.0, 8.0].contains(&v))
+            .then(|| format!("Invalid msaa sample count {v}, expected 1, 2, 4 or 8")),
+        SweepSetting::Cascades => {
+            (v.fract() != 0.0 || !(1.0..=MAX_CASCADES_PER_LIGHT as f32).contains(&v)).then(|| {
+                format!(
+                    "Invalid cascade count {v}, expected a whole number from 1 to \
+                    {MAX_CASCADES_PER_LIGHT}"
+                )
+            })
+        }
+        SweepSetting::ShadowDistance => (v <= first_cascade || !v.is_finite()).then(|| {
+            format!(
+                "Invalid shadow distance {v}, expected more than the first cascade's \
+                {first_cascade}"
+            )
+        }),
+    };
+    if let Some(error) = values.iter().find_map(|v| invalid(*v)) {
+        return Err(error);
+    }
+    Ok(Sweep { setting, values })
+}
+
+#[derive(Default)]
+pub struct SweepState {
+    settled_frames: u32,
+    /// Index of the value being measured, None until the first run starts
+    index: Option<usize>,
+    running: bool,
+    results: Vec<(f32, f32)>,
+    done: bool,
+}
+
+fn apply_sweep_value(
+    setting: SweepSetting,
+    value: f32,
+    msaa: &mut Msaa,
+    sun: &mut Query<&mut CascadeShadowConfig, With<Sun>>,
+) {
+    for mut cascades in sun.iter_mut() {
+        let (num_cascades, maximum_distance) = match setting {
+            SweepSetting::ShadowDistance => (cascades.bounds.len(), value),
+            SweepSetting::Cascades => (
+                value as usize,
+                cascades.bounds.last().copied().unwrap_or(40.0),
+            ),
+            SweepSetting::Msaa => continue,
+        };
+        *cascades = CascadeShadowConfigBuilder {
+            num_cascades,
+            maximum_distance,
+            ..default()
+        }
+        .into();
+    }
+    if setting == SweepSetting::Msaa {
+        *msaa = match value as u32 {
+            2 => Msaa::Sample2,
+            4 => Msaa::Sample4,
+            8 => Msaa::Sample8,
+            _ => Msaa::Off,
+        };
+    }
+}
+
+/// With `--sweep`, runs the benchmark once per value of the setting once the scene has loaded,
+/// then prints a table comparing the frame times.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn run_sweep(
+    mut state: Local<SweepState>,
+    args: Res<Args>,
+    mut msaa: ResMut<Msaa>,
+    mut sun: Query<&mut CascadeShadowConfig, With<Sun>>,
+    mut start: EventWriter<StartBenchmark>,
+    mut finished: EventReader<BenchmarkFinished>,
+) {
+    let Some(sweep) = &args.sweep else {
+        return;
+    };
+    if state.done {
+        return;
+    }
+    if state.running {
+        let Some(result) = finished.read().last() else {
+            return;
+        };
+        let value = sweep.values[state.index.unwrap()];
+        state.results.push((value, result.avg_frame_time_ms));
+        state.running = false;
+        state.settled_frames = 0;
+    }
+
+    if state.settled_frames == 0 {
+        let next = state.index.map_or(0, |i| i + 1);
+        if next == sweep.values.len() {
+            print_sweep_results(sweep.setting, &state.results);
+            state.done = true;
+            return;
+        }
+        if sweep.setting == SweepSetting::Msaa && (!args.minimal || args.deferred) {
+            error!(
+                "The msaa sweep needs --minimal and not --deferred, \
+                since TAA, SSAO and the deferred renderer require MSAA off"
+            );
+            state.done = true;
+            return;
+        }
+        info!("Sweep {} = {}", sweep.setting.name(), sweep.values[next]);
+        apply_sweep_value(sweep.setting, sweep.values[next], &mut msaa, &mut sun);
+        state.index = Some(next);
+    }
+    // Settles again after each value so the new pipelines and shadow maps are ready
+    state.settled_frames += 1;
+    if state.settled_frames >= SCENE_SETTLE_FRAMES {
+        start.send(StartBenchmark);
+        state.running = true;
+    }
+}
+
+fn print_sweep_results(setting: SweepSetting, results: &[(f32, f32)]) {
+    let Some((_, first)) = results.first() else {
+        return;
+    };
+    println!("Sweep results for {}:", setting.name());
+    println!(
+        "{:>12} {:>12} {:>10}",
+        setting.name(),
+        "frame time",
+        "change"
+    );
+    for (value, frame_time) in results {
+        println!(
+            "{:>12} {:>10.2}ms {:>+9.1}%",
+            value,
+            frame_time,
+            (frame_time / first - 1.0) * 100.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_values_the_cascade_builder_rejects_fail_to_parse() {
+        assert!(parse_sweep("cascades:1,2,4").is_ok());
+        assert!(parse_sweep("cascades:0").is_err());
+        assert!(parse_sweep("cascades:2.5").is_err());
+        assert!(parse_sweep("cascades:5").is_err());
+        assert!(parse_sweep("shadow-distance:10,40").is_ok());
+        assert!(parse_sweep("shadow-distance:0.1").is_err());
+        assert!(parse_sweep("shadow-distance:5").is_err());
+        assert!(parse_sweep("msaa:3").is_err());
+    }
+}
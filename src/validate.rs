@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    asset::LoadState,
+    prelude::*,
+    utils::HashMap,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_hidden_alley_scene::{
+    auto_instance::{
+        AutoInstanceMaterialPlugin, AutoInstanceMaterialRecursive, AutoInstanceMeshRecursive,
+        AutoInstancePlugin, AutoInstanceSettings,
+    },
+    PostProcScene, ProcScenePlugin, SceneSet,
+};
+
+use crate::Args;
+
+/// Seconds to wait for the scene and its assets before reporting whatever is still loading as
+/// failed.
+const VALIDATE_TIMEOUT_SECONDS: f32 = 300.0;
+
+/// Loads `--scene` without a window, runs `proc_scene` and instancing on it, then reports
+/// which meshes, materials and textures failed to load and exits, with an error if any did.
+pub fn run_validation(args: Args) {
+    println!("Validating {}", args.scene);
+    let mut app = App::new();
+    SceneSet::configure(&mut app);
+
+    app.insert_resource(AutoInstanceSettings {
+        warn_unmatched_distance: args.instance_warn_distance,
+        detect_scale: args.instance_detect_scale,
+        instance_by_material: args.instance_by_material,
+        ..default()
+    })
+    .insert_resource(args.proc_scene_settings())
+    .add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(args.log_plugin())
+            .disable::<WinitPlugin>(),
+    )
+    .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )))
+    .add_plugins((
+        ProcScenePlugin,
+        AutoInstancePlugin,
+        AutoInstanceMaterialPlugin::<StandardMaterial>::default(),
+    ))
+    .insert_resource(args)
+    .add_systems(Startup, spawn_scene)
+    .add_systems(Update, validate_assets.after(SceneSet::Instancing))
+    .run();
+}
+
+fn spawn_scene(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<Args>) {
+    commands.spawn((
+        SceneBundle {
+            scene: asset_server.load(format!("{}#Scene0", args.scene)),
+            ..default()
+        },
+        PostProcScene,
+        AutoInstanceMaterialRecursive,
+        AutoInstanceMeshRecursive,
+    ));
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AssetStatus {
+    Loaded,
+    Failed,
+    Loading,
+}
+
+fn asset_status<A: Asset>(
+    asset_server: &AssetServer,
+    assets: &Assets<A>,
+    handle: &Handle<A>,
+) -> AssetStatus {
+    if assets.contains(handle) {
+        AssetStatus::Loaded
+    } else if asset_server.load_state(handle) == LoadState::Failed {
+        AssetStatus::Failed
+    } else {
+        AssetStatus::Loading
+    }
+}
+
+fn asset_name<A: Asset>(asset_server: &AssetServer, handle: &Handle<A>) -> String {
+    asset_server
+        .get_path(handle)
+        .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string())
+}
+
+/// The status of each asset of one kind, by name so shared assets are only counted once.
+#[derive(Default)]
+struct AssetReport(HashMap<String, AssetStatus>);
+
+impl AssetReport {
+    fn add<A: Asset>(
+        &mut self,
+        asset_server: &AssetServer,
+        assets: &Assets<A>,
+        handle: &Handle<A>,
+    ) {
+        self.0.insert(
+            asset_name(asset_server, handle),
+            asset_status(asset_server, assets, handle),
+        );
+    }
+
+    fn count(&self, status: AssetStatus) -> usize {
+        self.0.values().filter(|s| **s == status).count()
+    }
+
+    fn failed(&self) -> Vec<&String> {
+        let mut failed = self
+            .0
+            .iter()
+            .filter(|(_, status)| **status != AssetStatus::Loaded)
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        failed.sort();
+        failed
+    }
+}
+
+type InstancingMarkers = Or<(
+    With<AutoInstanceMeshRecursive>,
+    With<AutoInstanceMaterialRecursive>,
+)>;
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn validate_assets(
+    time: Res<Time>,
+    args: Res<Args>,
+    asset_server: Res<AssetServer>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    images: Res<Assets<Image>>,
+    scenes: Query<&Handle<Scene>>,
+    pending: Query<(), Or<(With<PostProcScene>, InstancingMarkers)>>,
+    entities: Query<(Option<&Handle<Mesh>>, Option<&Handle<StandardMaterial>>)>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let timed_out = time.elapsed_seconds() > VALIDATE_TIMEOUT_SECONDS;
+    if scenes
+        .iter()
+        .any(|scene| asset_server.load_state(scene) == LoadState::Failed)
+    {
+        println!("Validation FAIL: {} failed to load", args.scene);
+        std::process::exit(1);
+    }
+    if !pending.is_empty() && !timed_out {
+        return;
+    }
+
+    let mut mesh_report = AssetReport::default();
+    let mut material_report = AssetReport::default();
+    let mut texture_report = AssetReport::default();
+    for (mesh, material) in &entities {
+        if let Some(mesh) = mesh {
+            mesh_report.add(&asset_server, &meshes, mesh);
+        }
+        let Some(material) = material else {
+            continue;
+        };
+        material_report.add(&asset_server, &materials, material);
+        let Some(material) = materials.get(material) else {
+            continue;
+        };
+        for texture in [
+            &material.base_color_texture,
+            &material.emissive_texture,
+            &material.metallic_roughness_texture,
+            &material.normal_map_texture,
+            &material.occlusion_texture,
+            &material.depth_map,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            texture_report.add(&asset_server, &images, texture);
+        }
+    }
+    let reports = [
+        ("Meshes", &mesh_report),
+        ("Materials", &material_report),
+        ("Textures", &texture_report),
+    ];
+    let still_loading = reports
+        .iter()
+        .any(|(_, report)| report.count(AssetStatus::Loading) > 0);
+    if still_loading && !timed_out {
+        return;
+    }
+
+    if timed_out {
+        println!("Validation timed out after {VALIDATE_TIMEOUT_SECONDS} seconds");
+    }
+    let mut passed = !timed_out || pending.is_empty();
+    println!("Validated {}:", args.scene);
+    for (kind, report) in &reports {
+        println!(
+            "  {kind}: {} loaded, {} failed, {} still loading",
+            report.count(AssetStatus::Loaded),
+            report.count(AssetStatus::Failed),
+            report.count(AssetStatus::Loading)
+        );
+    }
+    for (kind, report) in &reports {
+        for name in report.failed() {
+            println!("  {kind} not loaded: {name}");
+            passed = false;
+        }
+    }
+    println!("Validation {}", if passed { "PASS" } else { "FAIL" });
+    if !passed {
+        std::process::exit(1);
+    }
+    exit.send(AppExit);
+}
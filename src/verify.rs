@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use bevy::{prelude::*, render::camera::RenderTarget};
+use bevy_hidden_alley_scene::{
+    image_readback::readback_render_target, mipmap_generator::try_into_dynamic, ImageReadback,
+    SCENE_SETTLE_FRAMES,
+};
+use image::RgbaImage;
+
+use crate::{Args, BenchPositions, BenchmarkCamera};
+
+/// The image the benchmark camera renders into instead of the window while verifying.
+#[derive(Resource)]
+pub struct VerifyTarget(Handle<Image>);
+
+/// Frames to render at each position before capturing so TAA has converged.
+const VERIFY_WAIT_FRAMES: u32 = 60;
+
+#[derive(Default)]
+pub struct VerifyState {
+    settled_frames: u32,
+    position: usize,
+    frames: u32,
+    failed: bool,
+}
+
+/// Size of the offscreen captures, the same as the default window.
+const VERIFY_SIZE: (u32, u32) = (1920, 1080);
+
+/// Redirects the benchmark camera into an offscreen image that can be read back. Verification
+/// runs without a window, so it works without a display.
+pub fn setup_verify_target(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<&mut Camera, With<BenchmarkCamera>>,
+) {
+    let Ok(mut camera) = cameras.get_single_mut() else {
+        return;
+    };
+    let image = images.add(readback_render_target(VERIFY_SIZE.0, VERIFY_SIZE.1));
+    camera.target = RenderTarget::Image(image.clone());
+    commands.insert_resource(VerifyTarget(image));
+}
+
+/// Renders each benchmark position and compares it against `position_<n>.png` in the
+/// `--verify-against` directory, then exits with a non-zero code if any image differs by more
+/// than `--verify-tolerance` or has no reference. With `--update-references` the captures are
+/// saved as the new references instead.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_against_references(
+    mut state: Local<VerifyState>,
+    args: Res<Args>,
+    target: Option<Res<VerifyTarget>>,
+    mut readback: ResMut<ImageReadback>,
+    mut camera: Query<&mut Transform, With<BenchmarkCamera>>,
+    positions: Res<BenchPositions>,
+) {
+    let (Some(dir), Some(target)) = (&args.verify_against, target) else {
+        return;
+    };
+    if state.settled_frames < SCENE_SETTLE_FRAMES {
+        state.settled_frames += 1;
+        return;
+    }
+    let Ok(mut transform) = camera.get_single_mut() else {
+        return;
+    };
+
+    if state.frames == 0 {
+        *transform = positions.0[state.position];
+    }
+    state.frames += 1;
+    if state.frames == VERIFY_WAIT_FRAMES {
+        readback.request(&target.0);
+    }
+    if state.frames <= VERIFY_WAIT_FRAMES {
+        return;
+    }
+    let Some(image) = readback.take(&target.0) else {
+        if state.frames > VERIFY_WAIT_FRAMES * 4 {
+            error!("Timed out waiting for the verification readback");
+            std::process::exit(1);
+        }
+        return;
+    };
+
+    let path = Path::new(dir).join(format!("position_{}.png", state.position + 1));
+    if !compare_with_reference(image, &path, args.verify_tolerance, args.update_references) {
+        state.failed = true;
+    }
+    state.position += 1;
+    state.frames = 0;
+    if state.position == positions.0.len() {
+        if state.failed {
+            error!("Verification failed");
+            std::process::exit(1);
+        }
+        println!("Verification passed");
+        std::process::exit(0);
+    }
+}
+
+/// Returns false if the capture doesn't match the reference within the tolerance, or if it was
+/// saved as the reference when `update` is set and that failed.
+fn compare_with_reference(image: Image, path: &Path, tolerance: f32, update: bool) -> bool {
+    let capture = match try_into_dynamic(image) {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            error!("{}: failed to convert capture: {}", path.display(), e);
+            return false;
+        }
+    };
+    if update {
+        return match capture.save(path) {
+            Ok(_) => {
+                println!("{}: saved capture as the reference", path.display());
+                true
+            }
+            Err(e) => {
+                error!("{}: failed to save capture: {}", path.display(), e);
+                false
+            }
+        };
+    }
+    let reference = match image::open(path) {
+        Ok(reference) => reference.to_rgba8(),
+        Err(_) if !path.exists() => {
+            error!(
+                "{}: no reference, run with --update-references to save the capture",
+                path.display()
+            );
+            return false;
+        }
+        Err(e) => {
+            error!("{}: failed to load reference: {}", path.display(), e);
+            return false;
+        }
+    };
+    let Some((max, mean)) = pixel_error(&capture, &reference) else {
+        error!(
+            "{}: size mismatch, capture {:?} reference {:?}",
+            path.display(),
+            capture.dimensions(),
+            reference.dimensions()
+        );
+        return false;
+    };
+    let passed = mean <= tolerance;
+    println!(
+        "{}: max error {:.4}, mean error {:.4} {}",
+        path.display(),
+        max,
+        mean,
+        if passed { "ok" } else { "FAILED" }
+    );
+    passed
+}
+
+/// Max and mean absolute per channel difference, in the 0-1 range.
+fn pixel_error(a: &RgbaImage, b: &RgbaImage) -> Option<(f32, f32)> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    let mut max = 0u8;
+    let mut sum = 0u64;
+    for (a, b) in a.as_raw().iter().zip(b.as_raw()) {
+        let diff = a.abs_diff(*b);
+        max = max.max(diff);
+        sum += diff as u64;
+    }
+    let mean = sum as f32 / a.as_raw().len().max(1) as f32;
+    Some((max as f32 / 255.0, mean / 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn pixel_error_is_max_and_mean_channel_difference() {
+        let a = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        let mut b = a.clone();
+        b.put_pixel(1, 0, Rgba([255, 51, 0, 255]));
+        let (max, mean) = pixel_error(&a, &b).unwrap();
+        assert_eq!(max, 1.0);
+        // 306 over 8 channels
+        assert_eq!(mean, 306.0 / 8.0 / 255.0);
+        assert_eq!(pixel_error(&a, &a), Some((0.0, 0.0)));
+        assert_eq!(pixel_error(&a, &RgbaImage::new(1, 2)), None);
+    }
+}